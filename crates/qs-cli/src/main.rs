@@ -5,7 +5,7 @@ use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
-use qs_core::{Config, Indexer, QS_DIR, Searcher, discover};
+use qs_core::{Config, Indexer, QS_DIR, Reindexer, Searcher, discover};
 use syntect::easy::HighlightLines;
 use syntect::highlighting::ThemeSet;
 use syntect::parsing::SyntaxSet;
@@ -41,6 +41,13 @@ enum Commands {
     /// Re-index changed files
     Update,
 
+    /// Rebuild the index with another embedding model
+    Reindex {
+        /// Model to switch to (default: the one in the config)
+        #[arg(long)]
+        model: Option<String>,
+    },
+
     /// Find files similar to the given file
     Similar {
         /// File to find similar files for
@@ -51,6 +58,12 @@ enum Commands {
         limit: usize,
     },
 
+    /// Manage embedding models
+    Model {
+        #[command(subcommand)]
+        command: ModelCommands,
+    },
+
     /// Search for files matching a query
     Search {
         /// Search query
@@ -63,6 +76,30 @@ enum Commands {
         /// Number of context lines to show
         #[arg(short = 'C', long, default_value = "2")]
         context: usize,
+
+        /// Vector to search (repeat to fuse several; default: the main model's)
+        #[arg(long = "vector", value_name = "NAME")]
+        vectors: Vec<String>,
+
+        /// Rerank the top results with a cross-encoder (always on if the
+        /// config sets a reranker)
+        #[arg(long)]
+        rerank: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModelCommands {
+    /// Download the configured model into the model cache
+    Pull {
+        /// Model to download instead of the configured one
+        model: Option<String>,
+    },
+
+    /// Add models from a copy of another machine's model cache
+    Import {
+        /// Model cache directory, or one of its models--<org>--<name> directories
+        dir: PathBuf,
     },
 }
 
@@ -82,14 +119,21 @@ fn main() -> Result<()> {
         Some(Commands::Index { path }) => cmd_index(path)?,
         Some(Commands::Status) => cmd_status()?,
         Some(Commands::Update) => cmd_update()?,
+        Some(Commands::Reindex { model }) => cmd_reindex(model)?,
         Some(Commands::Similar { file, limit }) => cmd_similar(file, limit)?,
+        Some(Commands::Model { command }) => match command {
+            ModelCommands::Pull { model } => cmd_model_pull(model)?,
+            ModelCommands::Import { dir } => cmd_model_import(&dir)?,
+        },
         Some(Commands::Search {
             query,
             limit,
             context,
+            vectors,
+            rerank,
         }) => {
             let query = query.join(" ");
-            cmd_search(&query, limit, context)?;
+            cmd_search(&query, limit, context, &vectors, rerank)?;
         }
         None => {
             // Default: search with the provided query
@@ -99,7 +143,7 @@ fn main() -> Result<()> {
                 println!("Run 'qs --help' for more information.");
             } else {
                 let query = cli.query.join(" ");
-                cmd_search(&query, 10, 2)?;
+                cmd_search(&query, 10, 2, &[], false)?;
             }
         }
     }
@@ -197,12 +241,58 @@ fn cmd_status() -> Result<()> {
     println!("qs repository: {}", root.display());
     println!();
     println!("Configuration:");
-    println!("  Model: {}", config.model);
+    match &config.embedding {
+        qs_core::config::EmbeddingConfig::Fastembed => println!("  Model: {}", config.model),
+        qs_core::config::EmbeddingConfig::Http(http) => {
+            println!("  Model: {} ({})", http.model, http.url)
+        }
+    }
     println!("  Dimension: {}", config.dimension);
-    println!("  Chunk size: {} chars", config.chunk_size);
+    println!(
+        "  Model cache: {}",
+        qs_core::embed::cache::cache_dir(&config).display()
+    );
+    let unit = match config.chunk_unit {
+        qs_core::config::ChunkUnit::Chars => "chars",
+        qs_core::config::ChunkUnit::Tokens => "tokens",
+    };
+    println!("  Chunk size: {} {}", config.chunk_size, unit);
     println!("  Max file size: {} bytes", config.max_file_size);
+    let configured_storage = storage_summary(&config.storage);
+    println!("  Storage: {}", configured_storage);
     println!();
     println!("Index:");
+    let shard_dir = discover::shard_dir(&root);
+    for (name, shard_model) in qs_core::storage::ShardModel::load(&shard_dir)? {
+        let label = if name == qs_core::DEFAULT_VECTOR {
+            "Model".to_string()
+        } else {
+            format!("Vector {}", name)
+        };
+        println!(
+            "  {}: {} ({} dimensions)",
+            label, shard_model.model, shard_model.dimension
+        );
+    }
+    // The storage settings apply when the shard is built, so show its own
+    let mut storage_differs = false;
+    for (name, settings) in qs_core::Storage::built_settings(&shard_dir)? {
+        let label = if name == qs_core::DEFAULT_VECTOR {
+            "Storage".to_string()
+        } else {
+            format!("Storage of {}", name)
+        };
+        for storage in settings {
+            let summary = storage_summary(&storage);
+            storage_differs |= summary != configured_storage;
+            println!("  {}: {}", label, summary);
+        }
+    }
+    if storage_differs {
+        println!(
+            "  Warning: the index was built with other storage settings than configured. Run `qs reindex` to apply them"
+        );
+    }
     println!("  Files indexed: {}", file_index.files.len());
     println!(
         "  Total chunks: {}",
@@ -216,11 +306,110 @@ fn cmd_status() -> Result<()> {
     Ok(())
 }
 
+/// Summary of the storage settings that are fixed when a shard is built.
+fn storage_summary(storage: &qs_core::config::StorageConfig) -> String {
+    let quantization = match storage.quantization.as_ref().map(|q| q.method) {
+        None => "none".to_string(),
+        Some(qs_core::config::Quantization::Scalar) => "scalar".to_string(),
+        Some(qs_core::config::Quantization::Product { compression }) => {
+            format!("product ({:?})", compression).to_lowercase()
+        }
+        Some(qs_core::config::Quantization::Binary) => "binary".to_string(),
+    };
+    format!(
+        "{}, HNSW m={} ef_construct={}, quantization: {}",
+        if storage.on_disk {
+            "on disk"
+        } else {
+            "in memory"
+        },
+        storage.hnsw.m,
+        storage.hnsw.ef_construct,
+        quantization
+    )
+}
+
+/// Config of the current repository, or the default one outside of any, so
+/// that models can be staged before `qs init`.
+fn current_config() -> Result<Config> {
+    let cwd = std::env::current_dir()?;
+    match discover::find_qs_root(&cwd) {
+        Ok(root) => Ok(Config::load(&root)?),
+        Err(_) => Ok(Config::default()),
+    }
+}
+
+fn cmd_model_pull(model: Option<String>) -> Result<()> {
+    let mut config = current_config()?;
+    if let Some(model) = model {
+        config.set_model(model);
+    }
+
+    let cache_dir = qs_core::embed::cache::pull(&config)?;
+    println!("{} is in {}", config.model, cache_dir.display());
+
+    Ok(())
+}
+
+fn cmd_model_import(dir: &Path) -> Result<()> {
+    let config = current_config()?;
+    let cache_dir = qs_core::embed::cache::cache_dir(&config);
+
+    let names = qs_core::embed::cache::import(dir, &cache_dir)?;
+    for name in &names {
+        println!("Imported {}", name);
+    }
+    println!("Model cache: {}", cache_dir.display());
+
+    Ok(())
+}
+
 fn cmd_update() -> Result<()> {
     // Update is the same as index - it will skip unchanged files
     cmd_index(None)
 }
 
+fn cmd_reindex(model: Option<String>) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let root =
+        discover::find_qs_root(&cwd).context("Not in a qs repository. Run 'qs init' first.")?;
+
+    let mut config = Config::load(&root)?;
+    if let Some(model) = model {
+        config.set_model(model);
+    }
+
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("█▓░"),
+    );
+    pb.set_message("Embedding chunks...");
+
+    let mut reindexer = Reindexer::new(root, config)?;
+    reindexer.set_progress_callback(Box::new({
+        let pb = pb.clone();
+        move |event| {
+            if let qs_core::index::ProgressEvent::Embedding { current, total } = event {
+                pb.set_length(total as u64);
+                pb.set_position(current as u64);
+            }
+        }
+    }));
+
+    let stats = reindexer.run()?;
+
+    pb.finish_and_clear();
+
+    println!("✓ Reindexing complete:");
+    println!("  Previous model:    {}", stats.old_model);
+    println!("  Chunks reindexed:  {}", stats.chunks_reindexed);
+
+    Ok(())
+}
+
 fn cmd_similar(file: PathBuf, limit: usize) -> Result<()> {
     let cwd = std::env::current_dir()?;
     let root =
@@ -245,7 +434,13 @@ fn cmd_similar(file: PathBuf, limit: usize) -> Result<()> {
     Ok(())
 }
 
-fn cmd_search(query: &str, limit: usize, context_lines: usize) -> Result<()> {
+fn cmd_search(
+    query: &str,
+    limit: usize,
+    context_lines: usize,
+    vectors: &[String],
+    rerank: bool,
+) -> Result<()> {
     let cwd = std::env::current_dir()?;
     let root =
         discover::find_qs_root(&cwd).context("Not in a qs repository. Run 'qs init' first.")?;
@@ -258,8 +453,17 @@ fn cmd_search(query: &str, limit: usize, context_lines: usize) -> Result<()> {
     );
     pb.set_message("Searching...");
 
-    let searcher = Searcher::new(root.clone())?;
-    let results = searcher.search(query, limit)?;
+    let mut searcher = Searcher::new(root.clone())?;
+    if rerank && !searcher.reranks() {
+        let config = Config::load(&root)?;
+        let reranker = qs_core::embed::rerank::Reranker::new(
+            &config.reranker.clone().unwrap_or_default(),
+            &qs_core::embed::cache::cache_dir(&config),
+        )?;
+        searcher.set_reranker(reranker);
+    }
+    let vectors: Vec<&str> = vectors.iter().map(String::as_str).collect();
+    let results = searcher.search_vectors(query, &vectors, limit)?;
 
     pb.finish_and_clear();
 
@@ -295,16 +499,34 @@ fn print_result(
         "\x1b[31m" // Red for low scores
     };
 
-    // Header: index, score, file path, line range
+    // Header: index, score, file path, and location, page, cell or line range
+    let location = match (
+        &result.payload.location,
+        result.payload.page,
+        result.payload.cell,
+    ) {
+        (Some(location), _, _) => format!(" \x1b[33m{}\x1b[0m", location),
+        (None, Some(page), _) => format!(" \x1b[33mp.{}\x1b[0m", page),
+        (None, None, Some(cell)) => format!(
+            " \x1b[33mcell {}:{}-{}\x1b[0m",
+            cell, result.payload.start_line, result.payload.end_line
+        ),
+        (None, None, None) => format!(
+            ":\x1b[33m{}-{}\x1b[0m",
+            result.payload.start_line, result.payload.end_line
+        ),
+    };
+    let rerank_score = match result.rerank_score {
+        Some(score) => format!(" \x1b[2mrerank\x1b[0m {:.3}", score),
+        None => String::new(),
+    };
     println!(
-        "\x1b[1;36m[{}]\x1b[0m {}{:.3}\x1b[0m  \x1b[1m{}\x1b[0m:\x1b[33m{}-{}\x1b[0m",
-        index,
-        score_color,
-        result.score,
-        result.payload.path,
-        result.payload.start_line,
-        result.payload.end_line,
+        "\x1b[1;36m[{}]\x1b[0m {}{:.3}\x1b[0m{}  \x1b[1m{}\x1b[0m{}",
+        index, score_color, result.score, rerank_score, result.payload.path, location,
     );
+    if let Some(context) = &result.payload.context {
+        println!("\x1b[2m    § {}\x1b[0m", context);
+    }
 
     // Max lines to display before truncating
     const MAX_DISPLAY_LINES: usize = 12;
@@ -313,7 +535,8 @@ fn print_result(
 
     // Get the code with context
     let full_path = root.join(&result.payload.path);
-    let code_to_display = if full_path.exists() {
+    // Notebook line numbers are relative to the cell, not the JSON file
+    let code_to_display = if full_path.exists() && result.payload.cell.is_none() {
         // Try to read the file and get context lines
         if let Ok(content) = std::fs::read_to_string(&full_path) {
             let lines: Vec<&str> = content.lines().collect();
@@ -0,0 +1,233 @@
+//! Memory and recall of the storage settings, on synthetic embeddings
+//!
+//! Builds and optimizes a shard per setting from the same clustered unit
+//! vectors, then reports its size on disk, the resident memory it adds,
+//! search latency, and recall@10 against exact search. Run with
+//!
+//! ```text
+//! cargo bench -p qs-core --bench quantization [-- <points> [<dimension>]]
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::time::Instant;
+
+use qs_core::config::{Compression, HnswConfig, Quantization, QuantizationConfig, StorageConfig};
+use qs_core::storage::{ChunkPayload, NamedVectors, ShardModel, ShardModels};
+use qs_core::{DEFAULT_VECTOR, Storage};
+
+const DEFAULT_POINTS: usize = 20_000;
+const DEFAULT_DIMENSION: usize = 768;
+const QUERIES: usize = 100;
+const CLUSTERS: usize = 50;
+const TOP_K: usize = 10;
+const BATCH_SIZE: usize = 1_000;
+
+/// Deterministic xorshift generator, so every run indexes the same vectors.
+struct Rng(u64);
+
+impl Rng {
+    /// Uniform in [-1, 1).
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+
+    fn vector(&mut self, dimension: usize) -> Vec<f32> {
+        (0..dimension).map(|_| self.next()).collect()
+    }
+}
+
+fn normalize(mut v: Vec<f32>) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    v.iter_mut().for_each(|x| *x /= norm);
+    v
+}
+
+/// Unit vectors scattered around cluster centers, like embeddings of
+/// related chunks.
+fn clustered(rng: &mut Rng, centers: &[Vec<f32>], count: usize) -> Vec<Vec<f32>> {
+    (0..count)
+        .map(|i| {
+            let center = &centers[i % centers.len()];
+            let noise = rng.vector(center.len());
+            normalize(center.iter().zip(noise).map(|(c, n)| c + 0.6 * n).collect())
+        })
+        .collect()
+}
+
+/// IDs of the `TOP_K` points most similar to the query, by exact search.
+fn exact_top_k(points: &[Vec<f32>], query: &[f32]) -> HashSet<usize> {
+    let mut scored: Vec<(f32, usize)> = points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.iter().zip(query).map(|(a, b)| a * b).sum(), i))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(TOP_K).map(|(_, i)| i).collect()
+}
+
+/// Resident memory of this process, in MB (Linux only).
+fn resident_mb() -> Option<f64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: f64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * 4096.0 / 1e6)
+}
+
+fn disk_mb(path: &Path) -> f64 {
+    fn size(path: &Path) -> u64 {
+        match std::fs::read_dir(path) {
+            Ok(entries) => entries.flatten().map(|e| size(&e.path())).sum(),
+            Err(_) => path.metadata().map(|m| m.len()).unwrap_or(0),
+        }
+    }
+    size(path) as f64 / 1e6
+}
+
+fn quantized(method: Quantization, rescore: bool) -> StorageConfig {
+    StorageConfig {
+        quantization: Some(QuantizationConfig {
+            method,
+            always_ram: true,
+            rescore,
+            oversampling: Some(2.0),
+        }),
+        ..Default::default()
+    }
+}
+
+fn settings() -> Vec<(&'static str, StorageConfig)> {
+    vec![
+        ("on disk (default)", StorageConfig::default()),
+        (
+            "in memory",
+            StorageConfig {
+                on_disk: false,
+                ..Default::default()
+            },
+        ),
+        (
+            "hnsw m=32 ef=256",
+            StorageConfig {
+                hnsw: HnswConfig {
+                    m: 32,
+                    ef_construct: 200,
+                    ef: Some(256),
+                },
+                ..Default::default()
+            },
+        ),
+        ("scalar", quantized(Quantization::Scalar, true)),
+        (
+            "product x16",
+            quantized(
+                Quantization::Product {
+                    compression: Compression::X16,
+                },
+                true,
+            ),
+        ),
+        ("binary", quantized(Quantization::Binary, true)),
+        ("binary, no rescore", quantized(Quantization::Binary, false)),
+    ]
+}
+
+fn main() -> qs_core::Result<()> {
+    // `cargo bench` passes `--bench`, which is not a count
+    let mut args = std::env::args().skip(1).filter_map(|a| a.parse().ok());
+    let points = args.next().unwrap_or(DEFAULT_POINTS);
+    let dimension = args.next().unwrap_or(DEFAULT_DIMENSION);
+
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let centers: Vec<_> = (0..CLUSTERS).map(|_| rng.vector(dimension)).collect();
+    let vectors = clustered(&mut rng, &centers, points);
+    let queries = clustered(&mut rng, &centers, QUERIES);
+    let truth: Vec<_> = queries.iter().map(|q| exact_top_k(&vectors, q)).collect();
+
+    let models: ShardModels = BTreeMap::from([(
+        DEFAULT_VECTOR.to_string(),
+        ShardModel {
+            model: "synthetic".to_string(),
+            dimension,
+            document_template: None,
+        },
+    )]);
+
+    println!(
+        "{} points of {} dimensions, {} queries\n",
+        points, dimension, QUERIES
+    );
+    println!(
+        "{:<20} {:>9} {:>10} {:>10} {:>10} {:>10}",
+        "setting", "build s", "disk MB", "+RAM MB", "query ms", "recall@10"
+    );
+
+    for (name, config) in settings() {
+        let dir = std::env::temp_dir().join(format!("qs-bench-{}", name.replace(' ', "-")));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let start = Instant::now();
+        {
+            let storage = Storage::open_at(&dir, &models, &config)?;
+            for (batch, chunk) in vectors.chunks(BATCH_SIZE).enumerate() {
+                let points = chunk
+                    .iter()
+                    .enumerate()
+                    .map(|(i, vector)| {
+                        let id = batch * BATCH_SIZE + i;
+                        let vectors =
+                            NamedVectors::from([(DEFAULT_VECTOR.to_string(), vector.clone())]);
+                        let payload = ChunkPayload {
+                            chunk_index: id,
+                            ..Default::default()
+                        };
+                        (id as u64, vectors, payload)
+                    })
+                    .collect();
+                storage.upsert(points)?;
+            }
+            // Qdrant builds the HNSW graph and quantized vectors as it optimizes
+            storage.optimize()?;
+            storage.flush();
+        }
+        let build_secs = start.elapsed().as_secs_f64();
+
+        // Reopen, as a search would, and measure what the shard keeps resident
+        let before = resident_mb();
+        let (query_ms, after, found) = {
+            let storage = Storage::open_at(&dir, &models, &config)?;
+            let start = Instant::now();
+            let mut found = 0;
+            for (query, truth) in queries.iter().zip(&truth) {
+                let results = storage.search(DEFAULT_VECTOR, query.clone(), TOP_K)?;
+                found += results
+                    .iter()
+                    .filter(|r| truth.contains(&r.payload.chunk_index))
+                    .count();
+            }
+            let query_ms = start.elapsed().as_secs_f64() * 1e3 / QUERIES as f64;
+            (query_ms, resident_mb(), found)
+        };
+        let memory = match (before, after) {
+            (Some(before), Some(after)) => format!("{:.1}", after - before),
+            _ => "n/a".to_string(),
+        };
+        let recall = found as f64 / (QUERIES * TOP_K) as f64;
+
+        println!(
+            "{:<20} {:>9.1} {:>10.1} {:>10} {:>10.2} {:>10.3}",
+            name,
+            build_secs,
+            disk_mb(&dir),
+            memory,
+            query_ms,
+            recall
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,12 @@
+; Declarations, together with the comments directly above them
+(
+  (comment)* @doc
+  .
+  [
+    (function_declaration name: (identifier) @name)
+    (method_declaration name: (field_identifier) @name)
+    (type_declaration (type_spec name: (type_identifier) @name))
+    (const_declaration)
+    (var_declaration)
+  ] @chunk
+)
@@ -0,0 +1,15 @@
+; Declarations, together with the comments and type signature directly
+; above them. Function types have no name, so only definitions match.
+(
+  [(comment) (haddock) (signature)]* @doc
+  .
+  [
+    (function name: (_) @name)
+    (bind name: (_) @name)
+    (data_type name: (_) @name)
+    (newtype name: (_) @name)
+    (type_synomym name: (_) @name)
+    (class name: (_) @name)
+    (instance)
+  ] @chunk
+)
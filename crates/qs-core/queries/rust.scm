@@ -0,0 +1,17 @@
+; Items, together with the doc comments and attributes directly above them
+(
+  [(line_comment) (block_comment) (attribute_item)]* @doc
+  .
+  [
+    (function_item name: (identifier) @name)
+    (struct_item name: (type_identifier) @name)
+    (enum_item name: (type_identifier) @name)
+    (trait_item name: (type_identifier) @name)
+    (impl_item type: (_) @name)
+    (mod_item name: (identifier) @name)
+    (const_item name: (identifier) @name)
+    (static_item name: (identifier) @name)
+    (type_item name: (type_identifier) @name)
+    (macro_definition name: (identifier) @name)
+  ] @chunk
+)
@@ -0,0 +1,20 @@
+; Functions, tests and type declarations, together with the doc comments
+; directly above them. Other constants, such as imports, are left to the
+; fallback chunker.
+(
+  (comment)* @doc
+  .
+  [
+    (function_declaration (identifier) @name)
+    (variable_declaration
+      (identifier) @name
+      [
+        (struct_declaration)
+        (enum_declaration)
+        (union_declaration)
+        (opaque_declaration)
+        (function_signature)
+      ])
+    (test_declaration)
+  ] @chunk
+)
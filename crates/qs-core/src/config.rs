@@ -1,18 +1,22 @@
 //! Configuration handling for .qs/config.json
 
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    DEFAULT_CHUNK_OVERLAP, DEFAULT_CHUNK_SIZE, DEFAULT_DIM, DEFAULT_MAX_FILE_SIZE, DEFAULT_MODEL,
-    Result, discover,
+    DEFAULT_CHUNK_OVERLAP, DEFAULT_CHUNK_SIZE, DEFAULT_DIM, DEFAULT_HNSW_EF_CONSTRUCT,
+    DEFAULT_HNSW_M, DEFAULT_HTTP_BATCH_SIZE, DEFAULT_HTTP_RETRIES, DEFAULT_HTTP_TIMEOUT_SECS,
+    DEFAULT_MAX_FILE_SIZE, DEFAULT_MAX_LENGTH, DEFAULT_MODEL, DEFAULT_RERANK_CANDIDATES,
+    DEFAULT_RERANKER, DEFAULT_VECTOR, QsError, Result, discover,
 };
 
 /// Configuration stored in .qs/config.json
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    /// Embedding model name (fastembed model ID)
+    /// Embedding model name (fastembed model ID), or `path:<dir>` for a
+    /// local ONNX model
     #[serde(default = "default_model")]
     pub model: String,
 
@@ -20,14 +24,40 @@ pub struct Config {
     #[serde(default = "default_dim")]
     pub dimension: usize,
 
-    /// Chunk size in characters
+    /// Where embeddings come from (default: the local fastembed `model`)
+    #[serde(default, skip_serializing_if = "EmbeddingConfig::is_fastembed")]
+    pub embedding: EmbeddingConfig,
+
+    /// Directory fastembed models are downloaded to and loaded from
+    /// (default: fastembed's; overridden by `QS_MODEL_DIR`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_cache_dir: Option<PathBuf>,
+
+    /// Files and settings of a local model given as `path:<dir>`
+    #[serde(default, skip_serializing_if = "LocalModelConfig::is_default")]
+    pub local_model: LocalModelConfig,
+
+    /// Query and document templates of the model, overriding the built-in
+    /// ones for known models
+    #[serde(default, skip_serializing_if = "TemplateConfig::is_default")]
+    pub templates: TemplateConfig,
+
+    /// Cross-encoder reranking the top search results (default: none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reranker: Option<RerankerConfig>,
+
+    /// Chunk size, measured in `chunk_unit`
     #[serde(default = "default_chunk_size")]
     pub chunk_size: usize,
 
-    /// Chunk overlap in characters
+    /// Chunk overlap, measured in `chunk_unit`
     #[serde(default = "default_chunk_overlap")]
     pub chunk_overlap: usize,
 
+    /// Unit used to measure `chunk_size` and `chunk_overlap`
+    #[serde(default)]
+    pub chunk_unit: ChunkUnit,
+
     /// Maximum file size to index (bytes)
     #[serde(default = "default_max_file_size")]
     pub max_file_size: u64,
@@ -43,6 +73,367 @@ pub struct Config {
     /// Additional paths to ignore (on top of .gitignore)
     #[serde(default)]
     pub ignore_paths: Vec<String>,
+
+    /// Index the outputs of Jupyter notebook cells along with their source
+    #[serde(default)]
+    pub notebook_outputs: bool,
+
+    /// Index the text members of zip and tar archives, and gzip or zstd
+    /// compressed files (requires the `archive` feature)
+    #[serde(default)]
+    pub archives: bool,
+
+    /// Tree-sitter grammars to load from shared libraries at runtime
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grammars: Vec<GrammarConfig>,
+
+    /// Vector spaces stored alongside the default one of `model`, each
+    /// embedded with its own model
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vectors: Vec<VectorConfig>,
+
+    /// Vector storage, index and quantization settings, applied when the
+    /// shard is created
+    #[serde(default, skip_serializing_if = "StorageConfig::is_default")]
+    pub storage: StorageConfig,
+
+    /// Repository the config was loaded from, which relative paths in it
+    /// are resolved against (the current directory if unset)
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+/// Provider of embeddings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum EmbeddingConfig {
+    /// The fastembed model named by `model`, run locally
+    #[default]
+    Fastembed,
+    /// An OpenAI-compatible `/v1/embeddings` endpoint
+    Http(HttpEmbeddingConfig),
+}
+
+impl EmbeddingConfig {
+    fn is_fastembed(&self) -> bool {
+        matches!(self, Self::Fastembed)
+    }
+}
+
+/// An OpenAI-compatible embedding server, such as Ollama, llama.cpp server
+/// or text-embeddings-inference.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpEmbeddingConfig {
+    /// Server URL; `/v1/embeddings` is appended unless already present
+    pub url: String,
+
+    /// Model name sent with each request
+    pub model: String,
+
+    /// Extra request headers; `${VAR}` in values is replaced by the
+    /// environment variable, to keep API keys out of the config
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+
+    /// Maximum number of texts per request
+    #[serde(default = "default_http_batch_size")]
+    pub batch_size: usize,
+
+    /// Timeout of each request, in seconds
+    #[serde(default = "default_http_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// Retries of requests that fail to connect, time out, or get a 429 or
+    /// 5xx response
+    #[serde(default = "default_http_retries")]
+    pub retries: u32,
+}
+
+/// An additional named vector, embedding each chunk with another model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorConfig {
+    /// Vector name, as passed to `qs search --vector`
+    pub name: String,
+
+    /// Embedding model name, as the top-level `model`
+    pub model: String,
+
+    /// Embedding dimension, needed for the `http` provider
+    #[serde(default = "default_dim")]
+    pub dimension: usize,
+
+    /// Where embeddings come from (default: the local fastembed `model`)
+    #[serde(default, skip_serializing_if = "EmbeddingConfig::is_fastembed")]
+    pub embedding: EmbeddingConfig,
+
+    /// Query and document templates of the model
+    #[serde(default, skip_serializing_if = "TemplateConfig::is_default")]
+    pub templates: TemplateConfig,
+
+    /// Files and settings of a local `path:` model
+    #[serde(default, skip_serializing_if = "LocalModelConfig::is_default")]
+    pub local_model: LocalModelConfig,
+}
+
+/// How vectors are stored and indexed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// Keep vectors and the HNSW graph memory-mapped on disk rather than in
+    /// RAM
+    #[serde(default = "default_true")]
+    pub on_disk: bool,
+
+    /// HNSW graph settings
+    #[serde(default)]
+    pub hnsw: HnswConfig,
+
+    /// Compression of vectors for faster, smaller search (default: none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantization: Option<QuantizationConfig>,
+}
+
+impl StorageConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            on_disk: true,
+            hnsw: HnswConfig::default(),
+            quantization: None,
+        }
+    }
+}
+
+/// Settings of the HNSW graph used for approximate search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HnswConfig {
+    /// Edges per node; higher improves recall at the cost of memory
+    #[serde(default = "default_hnsw_m")]
+    pub m: usize,
+
+    /// Neighbours considered while building; higher improves recall at the
+    /// cost of indexing time
+    #[serde(default = "default_hnsw_ef_construct")]
+    pub ef_construct: usize,
+
+    /// Neighbours considered while searching (default: Qdrant's)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ef: Option<usize>,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: default_hnsw_m(),
+            ef_construct: default_hnsw_ef_construct(),
+            ef: None,
+        }
+    }
+}
+
+/// Quantization of stored vectors, searched first and then rescored with the
+/// original vectors.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuantizationConfig {
+    /// Quantization method
+    #[serde(flatten)]
+    pub method: Quantization,
+
+    /// Keep the quantized vectors in RAM, even if the originals are on disk
+    #[serde(default = "default_true")]
+    pub always_ram: bool,
+
+    /// Rescore the top results with the original vectors
+    #[serde(default = "default_true")]
+    pub rescore: bool,
+
+    /// Fetch this many times more results before rescoring (e.g. 2.0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oversampling: Option<f64>,
+}
+
+/// Quantization method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Quantization {
+    /// Each value as an 8-bit integer (4x smaller)
+    Scalar,
+    /// Groups of values as codebook entries
+    Product {
+        #[serde(default)]
+        compression: Compression,
+    },
+    /// Each value as one bit (32x smaller), for models with many dimensions
+    Binary,
+}
+
+/// Compression ratio of product quantization.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    X4,
+    X8,
+    #[default]
+    X16,
+    X32,
+    X64,
+}
+
+/// A cross-encoder reranking search results.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankerConfig {
+    /// Reranker model name (fastembed reranker ID)
+    #[serde(default = "default_reranker")]
+    pub model: String,
+
+    /// Number of results fetched by vector search and reranked
+    #[serde(default = "default_rerank_candidates")]
+    pub candidates: usize,
+}
+
+impl Default for RerankerConfig {
+    fn default() -> Self {
+        Self {
+            model: default_reranker(),
+            candidates: default_rerank_candidates(),
+        }
+    }
+}
+
+/// Templates wrapping texts before they are embedded, in which `{text}` is
+/// replaced by the text, e.g. `"query: {text}"`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateConfig {
+    /// Template of search queries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+
+    /// Template of indexed chunks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+}
+
+impl TemplateConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Files and settings of a local ONNX model, such as a fine-tuned model
+/// exported with `optimum-cli export onnx`.
+///
+/// Besides the ONNX and tokenizer files, the model directory must hold a
+/// `tokenizer_config.json` with a `pad_token`, and may hold `config.json` and
+/// `special_tokens_map.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalModelConfig {
+    /// ONNX model file, relative to the model directory
+    #[serde(default = "default_onnx_file")]
+    pub onnx_file: PathBuf,
+
+    /// Tokenizer file, relative to the model directory
+    #[serde(default = "default_tokenizer_file")]
+    pub tokenizer_file: PathBuf,
+
+    /// How token embeddings are pooled into one embedding per text
+    #[serde(default)]
+    pub pooling: Pooling,
+
+    /// Scale embeddings to unit length
+    #[serde(default = "default_true")]
+    pub normalize: bool,
+
+    /// Maximum number of tokens per text; longer texts are truncated
+    #[serde(default = "default_max_length")]
+    pub max_length: usize,
+}
+
+impl LocalModelConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Default for LocalModelConfig {
+    fn default() -> Self {
+        Self {
+            onnx_file: default_onnx_file(),
+            tokenizer_file: default_tokenizer_file(),
+            pooling: Pooling::default(),
+            normalize: true,
+            max_length: default_max_length(),
+        }
+    }
+}
+
+/// Pooling of token embeddings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    /// Average of all token embeddings
+    #[default]
+    Mean,
+    /// Embedding of the first (`[CLS]`) token
+    Cls,
+}
+
+/// A tree-sitter grammar loaded from a shared library at runtime.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrammarConfig {
+    /// Grammar name; the library must export `tree_sitter_<name>`
+    pub name: String,
+
+    /// File extensions parsed with this grammar
+    pub extensions: Vec<String>,
+
+    /// Shared library, relative to `.qs/grammars` (default: `libtree-sitter-<name>.so`,
+    /// `.dylib` on macOS, or `tree-sitter-<name>.dll` on Windows)
+    #[serde(default)]
+    pub library: Option<PathBuf>,
+
+    /// Chunk query file, relative to `.qs/queries` (default: `<name>.scm`)
+    #[serde(default)]
+    pub query: Option<PathBuf>,
+}
+
+impl GrammarConfig {
+    /// Path of the grammar's shared library.
+    pub fn library_path(&self, root: &Path) -> PathBuf {
+        let file = self.library.clone().unwrap_or_else(|| {
+            format!(
+                "{}tree-sitter-{}{}",
+                std::env::consts::DLL_PREFIX,
+                self.name,
+                std::env::consts::DLL_SUFFIX
+            )
+            .into()
+        });
+        discover::grammars_path(root).join(file)
+    }
+
+    /// Path of the grammar's chunk query.
+    pub fn query_path(&self, root: &Path) -> PathBuf {
+        let file = self
+            .query
+            .clone()
+            .unwrap_or_else(|| format!("{}.scm", self.name).into());
+        discover::queries_path(root).join(file)
+    }
+}
+
+/// Unit in which chunk sizes are measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkUnit {
+    /// Characters of source text
+    #[default]
+    Chars,
+    /// Tokens produced by the embedding model's tokenizer
+    Tokens,
 }
 
 fn default_model() -> String {
@@ -53,6 +444,50 @@ fn default_dim() -> usize {
     DEFAULT_DIM
 }
 
+fn default_hnsw_m() -> usize {
+    DEFAULT_HNSW_M
+}
+
+fn default_hnsw_ef_construct() -> usize {
+    DEFAULT_HNSW_EF_CONSTRUCT
+}
+
+fn default_reranker() -> String {
+    DEFAULT_RERANKER.to_string()
+}
+
+fn default_rerank_candidates() -> usize {
+    DEFAULT_RERANK_CANDIDATES
+}
+
+fn default_onnx_file() -> PathBuf {
+    PathBuf::from("model.onnx")
+}
+
+fn default_tokenizer_file() -> PathBuf {
+    PathBuf::from("tokenizer.json")
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_length() -> usize {
+    DEFAULT_MAX_LENGTH
+}
+
+fn default_http_batch_size() -> usize {
+    DEFAULT_HTTP_BATCH_SIZE
+}
+
+fn default_http_timeout_secs() -> u64 {
+    DEFAULT_HTTP_TIMEOUT_SECS
+}
+
+fn default_http_retries() -> u32 {
+    DEFAULT_HTTP_RETRIES
+}
+
 fn default_chunk_size() -> usize {
     DEFAULT_CHUNK_SIZE
 }
@@ -70,12 +505,24 @@ impl Default for Config {
         Self {
             model: default_model(),
             dimension: default_dim(),
+            embedding: EmbeddingConfig::default(),
+            model_cache_dir: None,
+            local_model: LocalModelConfig::default(),
+            templates: TemplateConfig::default(),
+            reranker: None,
             chunk_size: default_chunk_size(),
             chunk_overlap: default_chunk_overlap(),
+            chunk_unit: ChunkUnit::default(),
             max_file_size: default_max_file_size(),
             include_extensions: Vec::new(),
             exclude_extensions: Vec::new(),
             ignore_paths: Vec::new(),
+            notebook_outputs: false,
+            archives: false,
+            grammars: Vec::new(),
+            vectors: Vec::new(),
+            storage: StorageConfig::default(),
+            root: None,
         }
     }
 }
@@ -84,12 +531,57 @@ impl Config {
     /// Load config from the .qs directory.
     pub fn load(root: &Path) -> Result<Self> {
         let path = discover::config_path(root);
-        if path.exists() {
+        let config = if path.exists() {
             let content = std::fs::read_to_string(&path)?;
-            Ok(serde_json::from_str(&content)?)
+            serde_json::from_str(&content)?
         } else {
-            Ok(Self::default())
+            Self::default()
+        };
+        Ok(Self {
+            root: Some(root.to_path_buf()),
+            ..config
+        })
+    }
+
+    /// Switch to another fastembed model, dropping the endpoint and the
+    /// templates set for the previous model.
+    pub fn set_model(&mut self, model: String) {
+        self.model = model;
+        self.embedding = EmbeddingConfig::Fastembed;
+        self.templates = TemplateConfig::default();
+    }
+
+    /// Resolve a path from the config against the repository root.
+    pub fn resolve_path(&self, path: &Path) -> PathBuf {
+        match &self.root {
+            Some(root) => root.join(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// The config of each vector space, by name: the default one, then
+    /// those in `vectors` with their model in place of `model`.
+    pub fn vector_spaces(&self) -> Result<Vec<(String, Config)>> {
+        let mut spaces = vec![(DEFAULT_VECTOR.to_string(), self.clone())];
+        for vector in &self.vectors {
+            if spaces.iter().any(|(name, _)| *name == vector.name) {
+                return Err(QsError::Config(format!(
+                    "vector {} is defined twice",
+                    vector.name
+                )));
+            }
+            let config = Config {
+                model: vector.model.clone(),
+                dimension: vector.dimension,
+                embedding: vector.embedding.clone(),
+                templates: vector.templates.clone(),
+                local_model: vector.local_model.clone(),
+                vectors: Vec::new(),
+                ..self.clone()
+            };
+            spaces.push((vector.name.clone(), config));
         }
+        Ok(spaces)
     }
 
     /// Save config to the .qs directory.
@@ -100,3 +592,96 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_config() {
+        let config: Config = serde_json::from_str(
+            r#"{"storage": {
+                "on_disk": false,
+                "hnsw": {"m": 32, "ef": 128},
+                "quantization": {"type": "product", "compression": "x32", "oversampling": 2.0}
+            }}"#,
+        )
+        .unwrap();
+        let storage = &config.storage;
+        assert!(!storage.on_disk);
+        assert_eq!(storage.hnsw.m, 32);
+        assert_eq!(storage.hnsw.ef_construct, DEFAULT_HNSW_EF_CONSTRUCT);
+        assert_eq!(storage.hnsw.ef, Some(128));
+
+        let quantization = storage.quantization.as_ref().unwrap();
+        assert_eq!(
+            quantization.method,
+            Quantization::Product {
+                compression: Compression::X32
+            }
+        );
+        assert!(quantization.always_ram && quantization.rescore);
+        assert_eq!(quantization.oversampling, Some(2.0));
+
+        // Defaults are left out of saved configs
+        let saved = serde_json::to_value(Config::default()).unwrap();
+        assert!(saved.get("storage").is_none());
+    }
+
+    #[test]
+    fn test_vector_spaces() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "model": "path:models/code",
+                "local_model": {"pooling": "cls", "normalize": false},
+                "vectors": [
+                    {"name": "prose", "model": "path:models/prose", "local_model": {"max_length": 256}},
+                    {"name": "docs", "model": "bge-base-en-v1.5"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let spaces = config.vector_spaces().unwrap();
+        let names: Vec<_> = spaces.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, [DEFAULT_VECTOR, "prose", "docs"]);
+
+        // Each space has its own local model settings, not the default model's
+        let prose = &spaces[1].1;
+        assert_eq!(prose.model, "path:models/prose");
+        assert_eq!(prose.local_model.pooling, Pooling::Mean);
+        assert!(prose.local_model.normalize);
+        assert_eq!(prose.local_model.max_length, 256);
+        assert_eq!(spaces[2].1.local_model, LocalModelConfig::default());
+
+        let config = Config {
+            vectors: vec![config.vectors[0].clone(), config.vectors[0].clone()],
+            ..config
+        };
+        assert!(config.vector_spaces().is_err());
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let root = Path::new("/work/repo");
+        let config = Config::load(root).unwrap();
+        assert_eq!(
+            config.resolve_path(Path::new("models")),
+            root.join("models")
+        );
+        assert_eq!(
+            config.resolve_path(Path::new("/opt/models")),
+            Path::new("/opt/models")
+        );
+
+        // Outside of a repository, paths are left as they are
+        let config = Config::default();
+        assert_eq!(
+            config.resolve_path(Path::new("models")),
+            Path::new("models")
+        );
+
+        // The root isn't saved
+        let saved = serde_json::to_value(Config::load(root).unwrap()).unwrap();
+        assert!(saved.get("root").is_none());
+    }
+}
@@ -4,9 +4,39 @@ pub const QS_DIR: &str = ".qs";
 /// Default embedding model (code-optimized)
 pub const DEFAULT_MODEL: &str = "jina-embeddings-v2-base-code";
 
+/// Environment variable overriding the model cache directory
+pub const MODEL_DIR_ENV: &str = "QS_MODEL_DIR";
+
+/// Name of the vector embedded with the configured `model`
+pub const DEFAULT_VECTOR: &str = "chunks";
+
+/// Default cross-encoder for reranking search results
+pub const DEFAULT_RERANKER: &str = "bge-reranker-base";
+
+/// Default number of search results reranked
+pub const DEFAULT_RERANK_CANDIDATES: usize = 50;
+
 /// Default embedding dimension for jina-embeddings-v2-base-code
 pub const DEFAULT_DIM: usize = 768;
 
+/// Default maximum number of tokens per text for local models
+pub const DEFAULT_MAX_LENGTH: usize = 512;
+
+/// Default number of texts per request to an HTTP embedding server
+pub const DEFAULT_HTTP_BATCH_SIZE: usize = 32;
+
+/// Default timeout of requests to an HTTP embedding server, in seconds
+pub const DEFAULT_HTTP_TIMEOUT_SECS: u64 = 30;
+
+/// Default number of retries of failed requests to an HTTP embedding server
+pub const DEFAULT_HTTP_RETRIES: u32 = 3;
+
+/// Default number of HNSW graph edges per node
+pub const DEFAULT_HNSW_M: usize = 16;
+
+/// Default number of neighbours considered while building the HNSW graph
+pub const DEFAULT_HNSW_EF_CONSTRUCT: usize = 100;
+
 /// Default chunk size in characters (roughly ~512 tokens)
 pub const DEFAULT_CHUNK_SIZE: usize = 2000;
 
@@ -33,6 +33,17 @@ pub fn shard_dir(root: &Path) -> PathBuf {
     qs_dir(root).join("shard")
 }
 
+/// Get the directory a new shard is built in by `qs reindex`.
+pub fn reindex_shard_dir(root: &Path) -> PathBuf {
+    qs_dir(root).join("shard.new")
+}
+
+/// Get the directory the replaced shard is moved to by `qs reindex`, until
+/// the new one is in place.
+pub fn old_shard_dir(root: &Path) -> PathBuf {
+    qs_dir(root).join("shard.old")
+}
+
 /// Get the config file path.
 pub fn config_path(root: &Path) -> PathBuf {
     qs_dir(root).join("config.json")
@@ -43,6 +54,16 @@ pub fn files_path(root: &Path) -> PathBuf {
     qs_dir(root).join("files.json")
 }
 
+/// Get the directory of tree-sitter grammar libraries loaded at runtime.
+pub fn grammars_path(root: &Path) -> PathBuf {
+    qs_dir(root).join("grammars")
+}
+
+/// Get the directory of user-defined tree-sitter queries.
+pub fn queries_path(root: &Path) -> PathBuf {
+    qs_dir(root).join("queries")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
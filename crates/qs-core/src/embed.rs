@@ -1,60 +1,524 @@
-//! Embedding generation using fastembed
+//! Embedding generation: the backend trait, and its fastembed implementation
 
-use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+pub mod cache;
+pub mod http;
+pub mod rerank;
+pub mod templates;
 
+use std::borrow::Cow;
+use std::path::Path;
+
+use fastembed::{
+    EmbeddingModel, InitOptions, InitOptionsUserDefined, OutputKey, Pooling, TextEmbedding,
+    TokenizerFiles, UserDefinedEmbeddingModel,
+};
+use serde_json::Value;
+use tokenizers::Tokenizer;
+
+use crate::config::{self, EmbeddingConfig, LocalModelConfig};
+use crate::storage::{NamedVectors, ShardModel, ShardModels};
 use crate::{Config, QsError, Result};
+use templates::Templates;
+
+/// A model that turns text into vectors.
+///
+/// [`Embedder`] runs fastembed models locally; other runtimes, or
+/// deterministic doubles in tests, can be passed to
+/// [`Indexer::with_backend`](crate::Indexer::with_backend) and
+/// [`Searcher::with_backend`](crate::Searcher::with_backend).
+pub trait EmbeddingBackend: Send {
+    /// Generate embeddings for a batch of texts, one per text.
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>>;
+
+    /// Dimension of the generated embeddings.
+    fn dimension(&self) -> usize;
+
+    /// Identifier of the model, as written in the config.
+    fn model_id(&self) -> &str;
+
+    /// Generate embedding for a single text.
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let embeddings = self.embed_batch(&[text])?;
+        embeddings
+            .into_iter()
+            .next()
+            .ok_or_else(|| QsError::Embedding("No embedding generated".to_string()))
+    }
+
+    /// Templates the model expects queries and documents in, if any.
+    fn templates(&self) -> Option<&Templates> {
+        None
+    }
+
+    /// Generate embedding for a search query, in the query template.
+    fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
+        match self.templates() {
+            Some(templates) => self.embed(&templates.query(query)),
+            None => self.embed(query),
+        }
+    }
+
+    /// The text embedded for a document: the text in the document template.
+    fn document<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self.templates() {
+            Some(templates) => templates.document(text),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    /// Generate embeddings for documents to index, in the document template.
+    fn embed_documents(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        if self.templates().is_none() {
+            return self.embed_batch(texts);
+        }
+        let documents: Vec<_> = texts.iter().map(|t| self.document(t)).collect();
+        let documents: Vec<&str> = documents.iter().map(|d| d.as_ref()).collect();
+        self.embed_batch(&documents)
+    }
+
+    /// Whether `count_tokens` uses the model's tokenizer.
+    fn has_tokenizer(&self) -> bool {
+        false
+    }
+
+    /// Count the tokens the model sees for a text. Defaults to the
+    /// character count for backends without a tokenizer.
+    fn count_tokens(&self, text: &str) -> usize {
+        text.chars().count()
+    }
+
+    /// Maximum number of tokens the model embeds; longer inputs are truncated.
+    fn max_tokens(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Create the embedding backend specified in config.
+pub fn backend(config: &Config) -> Result<Box<dyn EmbeddingBackend>> {
+    match &config.embedding {
+        EmbeddingConfig::Fastembed => Ok(Box::new(Embedder::new(config)?)),
+        EmbeddingConfig::Http(http) => {
+            let templates = Templates::for_model(&http.model, &config.templates)?;
+            let backend = http::HttpBackend::new(http, config.dimension)?.with_templates(templates);
+            Ok(Box::new(backend))
+        }
+    }
+}
+
+/// An embedding backend, with the name of the vector it fills.
+pub struct VectorSpace {
+    pub name: String,
+    pub embedder: Box<dyn EmbeddingBackend>,
+}
+
+/// Create the embedding backend of each vector space in config, the
+/// default one first.
+pub fn vector_spaces(config: &Config) -> Result<Vec<VectorSpace>> {
+    config
+        .vector_spaces()?
+        .into_iter()
+        .map(|(name, config)| {
+            Ok(VectorSpace {
+                name,
+                embedder: backend(&config)?,
+            })
+        })
+        .collect()
+}
+
+/// The models of vector spaces, as recorded in a shard.
+pub fn shard_models(spaces: &[VectorSpace]) -> ShardModels {
+    spaces
+        .iter()
+        .map(|space| {
+            let model = ShardModel {
+                model: space.embedder.model_id().to_string(),
+                dimension: space.embedder.dimension(),
+                document_template: space
+                    .embedder
+                    .templates()
+                    .and_then(Templates::document_template)
+                    .map(str::to_string),
+            };
+            (space.name.clone(), model)
+        })
+        .collect()
+}
+
+/// Embed texts in every vector space, returning the named vectors of each
+/// text.
+pub fn embed_spaces(spaces: &[VectorSpace], texts: &[&str]) -> Result<Vec<NamedVectors>> {
+    let mut vectors = vec![NamedVectors::new(); texts.len()];
+    for space in spaces {
+        let embeddings = space.embedder.embed_documents(texts)?;
+        for (named, embedding) in vectors.iter_mut().zip(embeddings) {
+            named.insert(space.name.clone(), embedding);
+        }
+    }
+    Ok(vectors)
+}
+
+/// Prefix of `model` values naming a local model directory
+const PATH_PREFIX: &str = "path:";
+
+/// Built-in fastembed models, by config name
+const MODELS: &[(&str, EmbeddingModel)] = &[
+    // Code-optimized model (default)
+    (
+        "jina-embeddings-v2-base-code",
+        EmbeddingModel::JinaEmbeddingsV2BaseCode,
+    ),
+    // General-purpose models
+    ("all-MiniLM-L6-v2", EmbeddingModel::AllMiniLML6V2),
+    ("all-MiniLM-L12-v2", EmbeddingModel::AllMiniLML12V2),
+    ("bge-small-en-v1.5", EmbeddingModel::BGESmallENV15),
+    ("bge-base-en-v1.5", EmbeddingModel::BGEBaseENV15),
+];
+
+/// Model output holding the token embeddings: the first, as in fastembed
+const OUTPUT_PRECEDENCE: &[OutputKey] = &[OutputKey::OnlyOne];
 
 /// Wrapper around fastembed for generating embeddings.
 pub struct Embedder {
     model: TextEmbedding,
+    model_id: String,
+    dimension: usize,
+    /// Pooling of a local model whose embeddings are not normalized, which
+    /// fastembed always does
+    unnormalized_pooling: Option<Pooling>,
+    /// Copy of the model's tokenizer with truncation disabled, used for counting
+    tokenizer: Tokenizer,
+    /// Maximum number of tokens the model sees before truncating
+    max_tokens: Option<usize>,
+    templates: Templates,
 }
 
 impl Embedder {
     /// Create a new embedder with the model specified in config.
     pub fn new(config: &Config) -> Result<Self> {
-        let model_type = match config.model.as_str() {
-            // Code-optimized model (default)
-            "jina-embeddings-v2-base-code" => EmbeddingModel::JinaEmbeddingsV2BaseCode,
-            // General-purpose models
-            "all-MiniLM-L6-v2" => EmbeddingModel::AllMiniLML6V2,
-            "all-MiniLM-L12-v2" => EmbeddingModel::AllMiniLML12V2,
-            "bge-small-en-v1.5" => EmbeddingModel::BGESmallENV15,
-            "bge-base-en-v1.5" => EmbeddingModel::BGEBaseENV15,
-            other => {
-                return Err(QsError::Embedding(format!(
-                    "Unknown model: {}. Supported: jina-embeddings-v2-base-code, all-MiniLM-L6-v2, bge-small-en-v1.5",
-                    other
-                )));
+        let templates = Templates::for_model(&config.model, &config.templates)?;
+        let local = config.model.strip_prefix(PATH_PREFIX);
+        let (model, dimension) = match local {
+            Some(dir) => {
+                let dir = config.resolve_path(Path::new(dir));
+                (load_local(&dir, &config.local_model)?, None)
+            }
+            None => {
+                let (model, dimension) = load_builtin(&config.model, &cache::cache_dir(config))?;
+                (model, Some(dimension))
             }
         };
+        let unnormalized_pooling = local
+            .filter(|_| !config.local_model.normalize)
+            .map(|_| pooling(config.local_model.pooling));
+
+        // The model's own tokenizer truncates and pads, which would cap the counts
+        let mut tokenizer = model.tokenizer.clone();
+        let max_tokens = tokenizer.get_truncation().map(|t| t.max_length);
+        tokenizer
+            .with_truncation(None)
+            .map_err(|e| QsError::Embedding(e.to_string()))?
+            .with_padding(None);
+
+        let mut embedder = Self {
+            model,
+            model_id: config.model.clone(),
+            dimension: dimension.unwrap_or_default(),
+            unnormalized_pooling,
+            tokenizer,
+            max_tokens,
+            templates,
+        };
 
-        let model =
-            TextEmbedding::try_new(InitOptions::new(model_type).with_show_download_progress(true))
-                .map_err(|e| QsError::Embedding(e.to_string()))?;
+        // Local models are asked for their dimension
+        if dimension.is_none() {
+            embedder.dimension = embedder.embed("dimension")?.len();
+        }
 
-        Ok(Self { model })
+        Ok(embedder)
     }
+}
+
+/// Load a built-in model, downloading it to `cache_dir` on first use, with
+/// its dimension.
+fn load_builtin(name: &str, cache_dir: &Path) -> Result<(TextEmbedding, usize)> {
+    let Some((_, model_type)) = MODELS.iter().find(|(n, _)| *n == name) else {
+        let supported: Vec<&str> = MODELS.iter().map(|(n, _)| *n).collect();
+        return Err(QsError::Embedding(format!(
+            "Unknown model: {}. Supported: {}, or {}<dir> for a local ONNX model",
+            name,
+            supported.join(", "),
+            PATH_PREFIX
+        )));
+    };
 
-    /// Generate embeddings for a batch of texts.
-    pub fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+    let info =
+        TextEmbedding::get_model_info(model_type).map_err(|e| QsError::Embedding(e.to_string()))?;
+    cache::ensure_available(
+        cache_dir,
+        &info.model_code,
+        cache::is_cached(cache_dir, info),
+    )?;
+
+    let options = InitOptions::new(model_type.clone())
+        .with_cache_dir(cache_dir.to_path_buf())
+        .with_show_download_progress(true);
+    let model = TextEmbedding::try_new(options).map_err(|e| QsError::Embedding(e.to_string()))?;
+
+    Ok((model, info.dim))
+}
+
+/// Load a user-defined ONNX model from a directory.
+fn load_local(dir: &Path, options: &LocalModelConfig) -> Result<TextEmbedding> {
+    let read = |file: &Path| {
+        let path = dir.join(file);
+        std::fs::read(&path).map_err(|e| QsError::Embedding(format!("{}: {}", path.display(), e)))
+    };
+    let read_optional =
+        |file: &str| std::fs::read(dir.join(file)).unwrap_or_else(|_| b"{}".to_vec());
+
+    let tokenizer_files = TokenizerFiles {
+        tokenizer_file: read(&options.tokenizer_file)?,
+        config_file: read_optional("config.json"),
+        special_tokens_map_file: read_optional("special_tokens_map.json"),
+        tokenizer_config_file: tokenizer_config(
+            &read(Path::new("tokenizer_config.json"))?,
+            options.max_length,
+        )
+        .map_err(|e| {
+            let path = dir.join("tokenizer_config.json");
+            QsError::Embedding(format!("{}: {}", path.display(), e))
+        })?,
+    };
+
+    let model = UserDefinedEmbeddingModel::new(read(&options.onnx_file)?, tokenizer_files)
+        .with_pooling(pooling(options.pooling));
+    TextEmbedding::try_new_from_user_defined(
+        model,
+        InitOptionsUserDefined::new().with_max_length(options.max_length),
+    )
+    .map_err(|e| QsError::Embedding(format!("{}: {}", dir.display(), e)))
+}
+
+/// The fastembed pooling for a configured one.
+fn pooling(pooling: config::Pooling) -> Pooling {
+    match pooling {
+        config::Pooling::Mean => Pooling::Mean,
+        config::Pooling::Cls => Pooling::Cls,
+    }
+}
+
+/// Fill in the tokenizer config fields that fastembed expects (and panics
+/// without): a `model_max_length`, and the `pad_token` as a plain string
+/// rather than an added-token object.
+fn tokenizer_config(content: &[u8], max_length: usize) -> std::result::Result<Vec<u8>, String> {
+    let mut config: Value = serde_json::from_slice(content).map_err(|e| e.to_string())?;
+    let Some(fields) = config.as_object_mut() else {
+        return Err("expected a JSON object".to_string());
+    };
+
+    if !fields.get("model_max_length").is_some_and(Value::is_number) {
+        fields.insert("model_max_length".to_string(), max_length.into());
+    }
+    let pad_token = match fields.get("pad_token") {
+        Some(Value::String(token)) => token.clone(),
+        Some(Value::Object(token)) => match token.get("content") {
+            Some(Value::String(content)) => content.clone(),
+            _ => return Err("pad_token has no content".to_string()),
+        },
+        _ => return Err("missing pad_token".to_string()),
+    };
+    fields.insert("pad_token".to_string(), pad_token.into());
+
+    serde_json::to_vec(&config).map_err(|e| e.to_string())
+}
+
+impl EmbeddingBackend for Embedder {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
         if texts.is_empty() {
             return Ok(Vec::new());
         }
 
-        let embeddings = self
-            .model
-            .embed(texts.to_vec(), None)
-            .map_err(|e| QsError::Embedding(e.to_string()))?;
+        let embeddings = match &self.unnormalized_pooling {
+            None => self.model.embed(texts.to_vec(), None),
+            Some(pooling) => self
+                .model
+                .transform(texts.to_vec(), None)
+                .and_then(|output| {
+                    output.export_with_transformer(|batches| {
+                        let mut embeddings = Vec::new();
+                        for batch in batches {
+                            let pooled = batch.select_and_pool_output(
+                                &OUTPUT_PRECEDENCE,
+                                Some(pooling.clone()),
+                            )?;
+                            embeddings.extend(pooled.rows().into_iter().map(|row| row.to_vec()));
+                        }
+                        Ok(embeddings)
+                    })
+                }),
+        }
+        .map_err(|e| QsError::Embedding(e.to_string()))?;
 
         Ok(embeddings)
     }
 
-    /// Generate embedding for a single text.
-    pub fn embed(&self, text: &str) -> Result<Vec<f32>> {
-        let embeddings = self.embed_batch(&[text])?;
-        embeddings
-            .into_iter()
-            .next()
-            .ok_or_else(|| QsError::Embedding("No embedding generated".to_string()))
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    fn has_tokenizer(&self) -> bool {
+        true
+    }
+
+    /// Count the tokens the model's tokenizer produces for a text.
+    ///
+    /// Special tokens are not included. Falls back to the character count
+    /// if the text cannot be tokenized.
+    fn count_tokens(&self, text: &str) -> usize {
+        self.tokenizer
+            .encode_fast(text, false)
+            .map(|encoding| encoding.len())
+            .unwrap_or_else(|_| text.chars().count())
+    }
+
+    fn max_tokens(&self) -> Option<usize> {
+        self.max_tokens
+    }
+
+    fn templates(&self) -> Option<&Templates> {
+        Some(&self.templates).filter(|t| !t.is_empty())
+    }
+}
+
+/// Deterministic embedding backends for tests.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    /// Embeds texts as their length, in every dimension.
+    pub struct LengthBackend {
+        pub model: String,
+        pub dimension: usize,
+        pub templates: Templates,
+    }
+
+    impl LengthBackend {
+        /// A backend named after a model, in its built-in templates.
+        pub fn new(model: &str, dimension: usize) -> Self {
+            Self {
+                model: model.to_string(),
+                dimension,
+                templates: Templates::for_model(model, &Default::default()).unwrap(),
+            }
+        }
+    }
+
+    impl EmbeddingBackend for LengthBackend {
+        fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|t| vec![t.len() as f32; self.dimension])
+                .collect())
+        }
+
+        fn dimension(&self) -> usize {
+            self.dimension
+        }
+
+        fn model_id(&self) -> &str {
+            &self.model
+        }
+
+        fn templates(&self) -> Option<&Templates> {
+            Some(&self.templates).filter(|t| !t.is_empty())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::LengthBackend;
+    use super::*;
+
+    #[test]
+    fn test_unknown_model() {
+        let error = load_builtin("text-embedding-3-small", Path::new("."))
+            .err()
+            .unwrap();
+        let message = error.to_string();
+        for (name, _) in MODELS {
+            assert!(message.contains(name), "{}", message);
+        }
+        assert!(message.contains("path:<dir>"));
+    }
+
+    #[test]
+    fn test_tokenizer_config() {
+        let fixed = |content: &str| {
+            tokenizer_config(content.as_bytes(), 512)
+                .map(|bytes| serde_json::from_slice::<Value>(&bytes).unwrap())
+        };
+
+        // Added-token pad tokens are flattened, and the length filled in
+        let config = fixed(r#"{"pad_token": {"content": "<pad>", "lstrip": false}}"#).unwrap();
+        assert_eq!(config["pad_token"], "<pad>");
+        assert_eq!(config["model_max_length"], 512);
+
+        let config = fixed(r#"{"pad_token": "[PAD]", "model_max_length": 8192}"#).unwrap();
+        assert_eq!(config["model_max_length"], 8192);
+
+        assert!(fixed(r#"{"model_max_length": 512}"#).is_err());
+        assert!(fixed("[]").is_err());
+    }
+
+    #[test]
+    fn test_embed_spaces() {
+        let spaces = vec![
+            VectorSpace {
+                name: "chunks".to_string(),
+                embedder: Box::new(LengthBackend::new("length", 1)),
+            },
+            VectorSpace {
+                name: "prose".to_string(),
+                embedder: Box::new(LengthBackend::new("length", 1)),
+            },
+        ];
+        let vectors = embed_spaces(&spaces, &["a", "bb"]).unwrap();
+        assert_eq!(vectors.len(), 2);
+        assert_eq!(vectors[1]["chunks"], [2.0]);
+        assert_eq!(vectors[1]["prose"], [2.0]);
+
+        let models = shard_models(&spaces);
+        assert_eq!(models.keys().collect::<Vec<_>>(), ["chunks", "prose"]);
+        assert_eq!(models["prose"].model, "length");
+    }
+
+    #[test]
+    fn test_backend_defaults() {
+        let backend: Box<dyn EmbeddingBackend> = Box::new(LengthBackend::new("length", 1));
+        assert_eq!(backend.embed("fn main() {}").unwrap(), [12.0]);
+        assert_eq!(backend.embed_query("main").unwrap(), [4.0]);
+        assert_eq!(
+            backend.embed_documents(&["a", "bb"]).unwrap(),
+            [[1.0], [2.0]]
+        );
+        assert!(!backend.has_tokenizer());
+        assert_eq!(backend.count_tokens("café"), 4);
+        assert_eq!(backend.max_tokens(), None);
+    }
+
+    #[test]
+    fn test_templates() {
+        let backend = LengthBackend::new("e5-base-v2", 1);
+        // "query: main" and "passage: a"
+        assert_eq!(backend.embed_query("main").unwrap(), [11.0]);
+        assert_eq!(backend.embed_documents(&["a"]).unwrap(), [[10.0]]);
+        assert_eq!(backend.document("a"), "passage: a");
+        assert_eq!(backend.embed("a").unwrap(), [1.0]);
     }
 }
@@ -0,0 +1,292 @@
+//! Model cache: where fastembed models are downloaded to, and staging them
+//! for machines without network access
+//!
+//! Models are stored in the Hugging Face hub layout
+//! (`models--<org>--<name>/{blobs,refs,snapshots}`), so the cache filled by
+//! `qs model pull` on one machine can be copied to another and added there
+//! with `qs model import`.
+
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use fastembed::{EmbeddingModel, ModelInfo, RerankerModelInfo};
+use hf_hub::Cache;
+
+use super::rerank::Reranker;
+use super::{Embedder, PATH_PREFIX};
+use crate::config::EmbeddingConfig;
+use crate::{Config, MODEL_DIR_ENV, QsError, Result};
+
+/// Hub that fastembed downloads from, unless `HF_ENDPOINT` is set
+const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+
+/// How long to wait for the hub before deciding there is no network
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tokenizer files fastembed reads along with the model
+const TOKENIZER_FILES: [&str; 4] = [
+    "tokenizer.json",
+    "config.json",
+    "special_tokens_map.json",
+    "tokenizer_config.json",
+];
+
+/// Directory models are cached in: `QS_MODEL_DIR`, else the configured
+/// `model_cache_dir` (relative to the repository), else fastembed's default.
+pub fn cache_dir(config: &Config) -> PathBuf {
+    std::env::var_os(MODEL_DIR_ENV)
+        .map(PathBuf::from)
+        .or_else(|| {
+            config
+                .model_cache_dir
+                .as_deref()
+                .map(|d| config.resolve_path(d))
+        })
+        .unwrap_or_else(|| PathBuf::from(fastembed::get_cache_dir()))
+}
+
+/// Check if all the files of a model are in the cache.
+pub fn is_cached(cache_dir: &Path, info: &ModelInfo<EmbeddingModel>) -> bool {
+    repo_cached(
+        cache_dir,
+        &info.model_code,
+        &info.model_file,
+        &info.additional_files,
+    )
+}
+
+/// Check if all the files of a reranker model are in the cache.
+pub fn is_reranker_cached(cache_dir: &Path, info: &RerankerModelInfo) -> bool {
+    repo_cached(
+        cache_dir,
+        &info.model_code,
+        &info.model_file,
+        &info.additional_files,
+    )
+}
+
+/// Check if the tokenizer files, model file and additional files of a
+/// model repository are in the cache.
+fn repo_cached(
+    cache_dir: &Path,
+    model_code: &str,
+    model_file: &str,
+    additional_files: &[String],
+) -> bool {
+    let repo = Cache::new(cache_dir.to_path_buf()).model(model_code.to_string());
+    let mut files = TOKENIZER_FILES
+        .iter()
+        .copied()
+        .chain([model_file])
+        .chain(additional_files.iter().map(String::as_str));
+    files.all(|file| repo.get(file).is_some())
+}
+
+/// Check that a model is cached, or that it can be downloaded.
+///
+/// Without network access, fastembed would hang until the connection times
+/// out, so the hub is tried first with a short timeout.
+pub(super) fn ensure_available(cache_dir: &Path, model_code: &str, cached: bool) -> Result<()> {
+    let endpoint = std::env::var("HF_ENDPOINT").unwrap_or_else(|_| DEFAULT_ENDPOINT.to_string());
+    if cached || is_reachable(&endpoint) {
+        return Ok(());
+    }
+
+    Err(QsError::Embedding(format!(
+        "model {} is not in the model cache ({}) and {} cannot be reached. \
+         Run `qs model pull` on a machine with network access, then copy its model \
+         cache here and run `qs model import <dir>`",
+        model_code,
+        cache_dir.display(),
+        endpoint
+    )))
+}
+
+/// Check if a TCP connection can be made to the host of a URL.
+fn is_reachable(url: &str) -> bool {
+    let (rest, port) = match url.split_once("://") {
+        Some(("http", rest)) => (rest, 80),
+        Some((_, rest)) => (rest, 443),
+        None => (url, 443),
+    };
+    let host = rest.split('/').next().unwrap_or(rest);
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, port)
+    };
+
+    address
+        .to_socket_addrs()
+        .into_iter()
+        .flatten()
+        .any(|addr| TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).is_ok())
+}
+
+/// Download the configured model, and reranker if any, into the model cache,
+/// and return the cache directory.
+pub fn pull(config: &Config) -> Result<PathBuf> {
+    if let EmbeddingConfig::Http(http) = &config.embedding {
+        return Err(QsError::Config(format!(
+            "{} is served by {}, there is nothing to download",
+            http.model, http.url
+        )));
+    }
+    if config.model.starts_with(PATH_PREFIX) {
+        return Err(QsError::Config(format!(
+            "{} is a local model, there is nothing to download",
+            config.model
+        )));
+    }
+
+    // Loading the models downloads whatever is missing
+    Embedder::new(config)?;
+    if let Some(reranker) = &config.reranker {
+        Reranker::new(reranker, &cache_dir(config))?;
+    }
+    Ok(cache_dir(config))
+}
+
+/// Copy models from a directory into the model cache, and return their
+/// names.
+///
+/// The directory is either a copy of a model cache, holding
+/// `models--<org>--<name>` directories, or one such directory.
+pub fn import(from: &Path, cache_dir: &Path) -> Result<Vec<String>> {
+    let sources: Vec<PathBuf> = if is_model_dir(from) {
+        vec![from.to_path_buf()]
+    } else {
+        std::fs::read_dir(from)
+            .map_err(|e| QsError::Config(format!("{}: {}", from.display(), e)))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| is_model_dir(path))
+            .collect()
+    };
+    if sources.is_empty() {
+        return Err(QsError::Config(format!(
+            "{}: no models found (expected models--<org>--<name> directories)",
+            from.display()
+        )));
+    }
+
+    let mut names = Vec::new();
+    for source in sources {
+        let Some(name) = source.file_name() else {
+            continue;
+        };
+        copy_dir(&source, &cache_dir.join(name))?;
+
+        // models--jinaai--jina-embeddings-v2-base-code is jinaai/jina-embeddings-v2-base-code
+        let name = name.to_string_lossy();
+        let name = name.trim_start_matches("models--").replacen("--", "/", 1);
+        names.push(name);
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+fn is_model_dir(path: &Path) -> bool {
+    path.is_dir()
+        && path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with("models--"))
+}
+
+/// Copy a directory recursively, merging into any existing one. Snapshots
+/// link to blobs, so symlinks are copied as links.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    if to.symlink_metadata().is_ok() {
+        std::fs::remove_file(to)?;
+    }
+    std::os::unix::fs::symlink(std::fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::copy(from, to).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lay out a cached model as the hub does, with snapshot files linking
+    /// to blobs.
+    #[cfg(unix)]
+    fn stage_model(cache: &Path, info: &ModelInfo<EmbeddingModel>) {
+        let repo = cache.join(format!("models--{}", info.model_code.replace('/', "--")));
+        std::fs::create_dir_all(repo.join("refs")).unwrap();
+        std::fs::create_dir_all(repo.join("blobs")).unwrap();
+        std::fs::write(repo.join("refs/main"), "abc123").unwrap();
+
+        let files = TOKENIZER_FILES
+            .iter()
+            .copied()
+            .chain([info.model_file.as_str()]);
+        for (i, file) in files.enumerate() {
+            std::fs::write(repo.join(format!("blobs/{}", i)), "{}").unwrap();
+
+            // Links are relative to the snapshot, e.g. ../../blobs/0
+            let path = repo.join("snapshots/abc123").join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let depth = Path::new(file).components().count() + 1;
+            let link = format!("{}blobs/{}", "../".repeat(depth), i);
+            std::os::unix::fs::symlink(link, path).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_import() {
+        let info = fastembed::TextEmbedding::get_model_info(&EmbeddingModel::AllMiniLML6V2)
+            .unwrap()
+            .clone();
+        let dir = std::env::temp_dir().join(format!("qs-model-import-{}", std::process::id()));
+        let staged = dir.join("staged");
+        let cache = dir.join("cache");
+        stage_model(&staged, &info);
+
+        assert!(is_cached(&staged, &info));
+        assert!(!is_cached(&cache, &info));
+
+        let names = import(&staged, &cache).unwrap();
+        assert_eq!(names, [info.model_code.as_str()]);
+
+        // Importing again merges into the existing copy
+        assert!(import(&staged, &cache).is_ok());
+        assert!(import(&dir.join("missing"), &cache).is_err());
+
+        // The copy stands on its own
+        std::fs::remove_dir_all(&staged).unwrap();
+        assert!(is_cached(&cache, &info));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unreachable() {
+        // Nothing listens on port 9 of localhost
+        assert!(!is_reachable("http://127.0.0.1:9/models"));
+    }
+}
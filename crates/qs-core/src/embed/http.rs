@@ -0,0 +1,376 @@
+//! Embeddings from an OpenAI-compatible HTTP server
+//!
+//! Texts are sent in batches to `POST /v1/embeddings` as
+//! `{"model": ..., "input": [...]}`, and the vectors are read back from
+//! `data[].embedding` in `data[].index` order. Requests that fail to connect,
+//! time out, or get a 429 or 5xx response are retried with exponential
+//! backoff.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use super::EmbeddingBackend;
+use super::templates::Templates;
+use crate::config::HttpEmbeddingConfig;
+use crate::{QsError, Result};
+
+/// Delay before the first retry, doubled for each one after it
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay between retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Client for an OpenAI-compatible embedding endpoint.
+pub struct HttpBackend {
+    agent: ureq::Agent,
+    url: String,
+    model: String,
+    headers: Vec<(String, String)>,
+    batch_size: usize,
+    retries: u32,
+    dimension: usize,
+    templates: Templates,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: usize,
+}
+
+impl HttpBackend {
+    /// Create a client for the configured server, expecting embeddings of
+    /// `dimension` values.
+    pub fn new(config: &HttpEmbeddingConfig, dimension: usize) -> Result<Self> {
+        let headers = config
+            .headers
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), expand_env(value)?)))
+            .collect::<Result<_>>()?;
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build();
+
+        Ok(Self {
+            agent,
+            url: endpoint(&config.url),
+            model: config.model.clone(),
+            headers,
+            batch_size: config.batch_size.max(1),
+            retries: config.retries,
+            dimension,
+            templates: Templates::default(),
+        })
+    }
+
+    /// Wrap queries and documents in the given templates.
+    pub fn with_templates(mut self, templates: Templates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// Embed one batch, retrying failed requests.
+    fn request(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let body = json!({ "model": self.model, "input": texts });
+
+        let mut attempt = 0;
+        let response = loop {
+            let mut request = self.agent.post(&self.url);
+            for (name, value) in &self.headers {
+                request = request.set(name, value);
+            }
+
+            let error = match request.send_json(&body) {
+                Ok(response) => break response,
+                Err(ureq::Error::Status(status, response)) if status == 429 || status >= 500 => {
+                    status_error(status, response)
+                }
+                Err(ureq::Error::Status(status, response)) => {
+                    return Err(self.error(status_error(status, response)));
+                }
+                Err(ureq::Error::Transport(e)) => e.to_string(),
+            };
+            if attempt >= self.retries {
+                return Err(self.error(error));
+            }
+            tracing::warn!("{}: {}, retrying", self.url, error);
+            std::thread::sleep(retry_delay(attempt));
+            attempt += 1;
+        };
+
+        let mut data = response
+            .into_json::<EmbeddingResponse>()
+            .map_err(|e| self.error(format!("invalid response: {}", e)))?
+            .data;
+        if data.len() != texts.len() {
+            return Err(self.error(format!(
+                "got {} embeddings for {} texts",
+                data.len(),
+                texts.len()
+            )));
+        }
+        data.sort_by_key(|d| d.index);
+
+        data.into_iter()
+            .map(|d| {
+                if d.embedding.len() == self.dimension {
+                    Ok(d.embedding)
+                } else {
+                    Err(self.error(format!(
+                        "got {}-dimensional embeddings, but the config dimension is {}",
+                        d.embedding.len(),
+                        self.dimension
+                    )))
+                }
+            })
+            .collect()
+    }
+
+    fn error(&self, message: String) -> QsError {
+        QsError::Embedding(format!("{}: {}", self.url, message))
+    }
+}
+
+impl EmbeddingBackend for HttpBackend {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size) {
+            embeddings.extend(self.request(batch)?);
+        }
+        Ok(embeddings)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    fn templates(&self) -> Option<&Templates> {
+        Some(&self.templates).filter(|t| !t.is_empty())
+    }
+}
+
+/// The embeddings endpoint of a server URL, which may be the server root,
+/// its `/v1` API root or the endpoint itself.
+fn endpoint(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    if url.ends_with("/embeddings") {
+        url.to_string()
+    } else if url.ends_with("/v1") {
+        format!("{}/embeddings", url)
+    } else {
+        format!("{}/v1/embeddings", url)
+    }
+}
+
+/// Describe an error response, with the start of its body.
+fn status_error(status: u16, response: ureq::Response) -> String {
+    let body = response.into_string().unwrap_or_default();
+    let body: String = body.trim().chars().take(200).collect();
+    format!("HTTP {} {}", status, body)
+}
+
+/// Replace `${VAR}` with the value of environment variable `VAR`.
+fn expand_env(value: &str) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + len];
+        let var = std::env::var(name)
+            .map_err(|_| QsError::Config(format!("environment variable {} is not set", name)))?;
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&var);
+        rest = &rest[start + len + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Delay before the retry numbered `attempt`, counting from zero.
+fn retry_delay(attempt: u32) -> Duration {
+    2u32.checked_pow(attempt)
+        .and_then(|factor| RETRY_DELAY.checked_mul(factor))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    use serde_json::Value;
+
+    use super::*;
+
+    /// A request received by the mock server: its headers and JSON body.
+    type Received = (String, Value);
+
+    /// Serve `count` requests on a local port, answering each with the
+    /// status and body returned by `respond`.
+    fn mock_server(
+        count: usize,
+        respond: impl Fn(usize, &Value) -> (u16, Value) + Send + 'static,
+    ) -> (String, JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            for i in 0..count {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+
+                let mut head = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_once(':').unwrap_or_default();
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    head.push_str(&line);
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let body: Value = serde_json::from_slice(&body).unwrap();
+
+                let (status, response) = respond(i, &body);
+                let response = response.to_string();
+                write!(
+                    &stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                )
+                .unwrap();
+                received.push((head, body));
+            }
+            received
+        });
+
+        (url, handle)
+    }
+
+    /// Respond with embeddings of each input's length, in reverse order.
+    fn embeddings(body: &Value) -> Value {
+        let data: Vec<Value> = body["input"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, text)| {
+                let len = text.as_str().unwrap().len() as f32;
+                json!({ "index": i, "embedding": [len, 0.0] })
+            })
+            .collect();
+        json!({ "data": data })
+    }
+
+    fn config(url: String) -> HttpEmbeddingConfig {
+        HttpEmbeddingConfig {
+            url,
+            model: "nomic-embed-text".to_string(),
+            headers: BTreeMap::new(),
+            batch_size: 2,
+            timeout_secs: 5,
+            retries: 1,
+        }
+    }
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(
+            endpoint("http://localhost:11434"),
+            "http://localhost:11434/v1/embeddings"
+        );
+        assert_eq!(
+            endpoint("https://api.openai.com/v1/"),
+            "https://api.openai.com/v1/embeddings"
+        );
+        assert_eq!(
+            endpoint("http://tei:8080/v1/embeddings"),
+            "http://tei:8080/v1/embeddings"
+        );
+    }
+
+    #[test]
+    fn test_embed_batches() {
+        let (url, server) = mock_server(2, |_, body| (200, embeddings(body)));
+        let mut config = config(url);
+        config
+            .headers
+            .insert("Authorization".to_string(), "Bearer secret".to_string());
+
+        let backend = HttpBackend::new(&config, 2).unwrap();
+        let vectors = backend.embed_batch(&["a", "bb", "ccc"]).unwrap();
+        assert_eq!(vectors, [[1.0, 0.0], [2.0, 0.0], [3.0, 0.0]]);
+
+        let received = server.join().unwrap();
+        assert!(received[0].0.starts_with("POST /v1/embeddings "));
+        assert!(received[0].0.contains("Authorization: Bearer secret"));
+        assert_eq!(received[0].1["model"], "nomic-embed-text");
+        assert_eq!(received[0].1["input"], json!(["a", "bb"]));
+        assert_eq!(received[1].1["input"], json!(["ccc"]));
+    }
+
+    #[test]
+    fn test_retry() {
+        let (url, server) = mock_server(3, |i, body| match i {
+            0 => (503, json!({ "error": "loading model" })),
+            1 => (200, embeddings(body)),
+            _ => (400, json!({ "error": "bad input" })),
+        });
+        let backend = HttpBackend::new(&config(url), 2).unwrap();
+
+        // Unavailable servers are retried, bad requests are not
+        assert_eq!(backend.embed("fn").unwrap(), [2.0, 0.0]);
+        let error = backend.embed("fn").unwrap_err().to_string();
+        assert!(error.contains("HTTP 400"), "{}", error);
+        server.join().unwrap();
+
+        // Delays double up to a cap, however many retries are configured
+        assert_eq!(retry_delay(0), RETRY_DELAY);
+        assert_eq!(retry_delay(2), RETRY_DELAY * 4);
+        assert_eq!(retry_delay(40), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let (url, server) = mock_server(1, |_, body| (200, embeddings(body)));
+        let backend = HttpBackend::new(&config(url), 768).unwrap();
+
+        let error = backend.embed("fn").unwrap_err().to_string();
+        assert!(error.contains("2-dimensional"), "{}", error);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_expand_env() {
+        assert_eq!(expand_env("Bearer abc").unwrap(), "Bearer abc");
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(expand_env("x${PATH}y").unwrap(), format!("x{}y", path));
+        assert!(expand_env("${QS_TEST_UNSET_VARIABLE}").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! Cross-encoder reranking of search results
+//!
+//! A cross-encoder reads the query and a chunk together, which ranks the
+//! top results more precisely than comparing their embeddings, but is too
+//! slow to run over the whole index.
+
+use std::path::Path;
+
+use fastembed::{RerankInitOptions, RerankerModel, TextRerank};
+
+use super::cache;
+use crate::config::RerankerConfig;
+use crate::storage::SearchResult;
+use crate::{QsError, Result};
+
+/// Built-in fastembed rerankers, by config name
+const MODELS: &[(&str, RerankerModel)] = &[
+    ("bge-reranker-base", RerankerModel::BGERerankerBase),
+    ("bge-reranker-v2-m3", RerankerModel::BGERerankerV2M3),
+    (
+        "jina-reranker-v1-turbo-en",
+        RerankerModel::JINARerankerV1TurboEn,
+    ),
+    (
+        "jina-reranker-v2-base-multilingual",
+        RerankerModel::JINARerankerV2BaseMultiligual,
+    ),
+];
+
+/// Wrapper around a fastembed cross-encoder.
+pub struct Reranker {
+    model: TextRerank,
+    candidates: usize,
+}
+
+impl Reranker {
+    /// Load the configured reranker, downloading it to `cache_dir` on first
+    /// use.
+    pub fn new(config: &RerankerConfig, cache_dir: &Path) -> Result<Self> {
+        let Some((_, model_type)) = MODELS.iter().find(|(n, _)| *n == config.model) else {
+            let supported: Vec<&str> = MODELS.iter().map(|(n, _)| *n).collect();
+            return Err(QsError::Embedding(format!(
+                "Unknown reranker: {}. Supported: {}",
+                config.model,
+                supported.join(", ")
+            )));
+        };
+
+        let info = TextRerank::get_model_info(model_type);
+        cache::ensure_available(
+            cache_dir,
+            &info.model_code,
+            cache::is_reranker_cached(cache_dir, &info),
+        )?;
+
+        let options = RerankInitOptions::new(model_type.clone())
+            .with_cache_dir(cache_dir.to_path_buf())
+            .with_show_download_progress(true);
+        let model = TextRerank::try_new(options).map_err(|e| QsError::Embedding(e.to_string()))?;
+
+        Ok(Self {
+            model,
+            candidates: config.candidates,
+        })
+    }
+
+    /// Number of results to fetch for reranking `limit` of them.
+    pub fn candidates(&self, limit: usize) -> usize {
+        self.candidates.max(limit)
+    }
+
+    /// Score each result against the query, and keep the best `limit` by
+    /// that score.
+    pub fn rerank(
+        &self,
+        query: &str,
+        results: Vec<SearchResult>,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        if results.is_empty() {
+            return Ok(results);
+        }
+
+        let texts: Vec<_> = results.iter().map(|r| r.payload.embedding_text()).collect();
+        let documents: Vec<&str> = texts.iter().map(|t| t.as_ref()).collect();
+        let scores = self
+            .model
+            .rerank(query, documents, false, None)
+            .map_err(|e| QsError::Embedding(e.to_string()))?;
+
+        let mut ranked = vec![0.0; results.len()];
+        for score in scores {
+            ranked[score.index] = score.score;
+        }
+        Ok(apply_scores(results, ranked, limit))
+    }
+}
+
+/// Set the rerank score of each result, and keep the best `limit` by it.
+fn apply_scores(results: Vec<SearchResult>, scores: Vec<f32>, limit: usize) -> Vec<SearchResult> {
+    let mut scored: Vec<(f32, SearchResult)> = scores.into_iter().zip(results).collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(score, mut result)| {
+            result.rerank_score = Some(score);
+            result
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::ChunkPayload;
+
+    fn result(path: &str, score: f32) -> SearchResult {
+        SearchResult {
+            score,
+            rerank_score: None,
+            payload: ChunkPayload {
+                path: path.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_apply_scores() {
+        let results = vec![
+            result("a.rs", 0.9),
+            result("b.rs", 0.8),
+            result("c.rs", 0.7),
+        ];
+        let reranked = apply_scores(results, vec![-2.0, 1.5, 3.0], 2);
+
+        // Both scores are kept
+        let ranked: Vec<_> = reranked
+            .iter()
+            .map(|r| (r.payload.path.as_str(), r.score, r.rerank_score))
+            .collect();
+        assert_eq!(ranked, [("c.rs", 0.7, Some(3.0)), ("b.rs", 0.8, Some(1.5))]);
+    }
+
+    #[test]
+    fn test_unknown_reranker() {
+        let config = RerankerConfig {
+            model: "ms-marco-MiniLM".to_string(),
+            ..Default::default()
+        };
+        let error = Reranker::new(&config, Path::new(".")).err().unwrap();
+        assert!(error.to_string().contains("bge-reranker-base"), "{}", error);
+    }
+}
@@ -0,0 +1,151 @@
+//! Query and document templates for models trained with prefixes or
+//! instructions
+//!
+//! Asymmetric models such as e5, bge and nomic embed queries and documents
+//! differently, and expect each to start with a marker like `query: `.
+//! Templates for known models are looked up by model name, and can be
+//! overridden in the config.
+
+use std::borrow::Cow;
+
+use crate::config::TemplateConfig;
+use crate::{QsError, Result};
+
+/// Placeholder replaced by the text in a template
+const PLACEHOLDER: &str = "{text}";
+
+/// Instruction of the bge family for retrieval queries
+const BGE_QUERY: &str = "Represent this sentence for searching relevant passages: {text}";
+
+/// Known models, by a part of their name: query and document templates
+const BUILTIN: &[(&str, Option<&str>, Option<&str>)] = &[
+    ("bge-small-en", Some(BGE_QUERY), None),
+    ("bge-base-en", Some(BGE_QUERY), None),
+    ("bge-large-en", Some(BGE_QUERY), None),
+    ("mxbai-embed-large", Some(BGE_QUERY), None),
+    ("snowflake-arctic-embed", Some(BGE_QUERY), None),
+    (
+        "nomic-embed-text",
+        Some("search_query: {text}"),
+        Some("search_document: {text}"),
+    ),
+    (
+        "multilingual-e5",
+        Some("query: {text}"),
+        Some("passage: {text}"),
+    ),
+    ("e5-small", Some("query: {text}"), Some("passage: {text}")),
+    ("e5-base", Some("query: {text}"), Some("passage: {text}")),
+    ("e5-large", Some("query: {text}"), Some("passage: {text}")),
+];
+
+/// Templates wrapping queries and documents before they are embedded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Templates {
+    query: Option<String>,
+    document: Option<String>,
+}
+
+impl Templates {
+    /// Templates of a model: the configured ones, else the built-in ones for
+    /// the model name.
+    pub fn for_model(model: &str, config: &TemplateConfig) -> Result<Self> {
+        let name = model.to_lowercase();
+        let (query, document) = BUILTIN
+            .iter()
+            .find(|(pattern, _, _)| name.contains(pattern))
+            .map(|(_, query, document)| (*query, *document))
+            .unwrap_or_default();
+
+        let templates = Self {
+            query: config.query.as_deref().or(query).map(str::to_string),
+            document: config.document.as_deref().or(document).map(str::to_string),
+        };
+        for template in [&templates.query, &templates.document]
+            .into_iter()
+            .flatten()
+        {
+            if !template.contains(PLACEHOLDER) {
+                return Err(QsError::Config(format!(
+                    "template {:?} has no {} placeholder",
+                    template, PLACEHOLDER
+                )));
+            }
+        }
+
+        Ok(templates)
+    }
+
+    /// Check if no template applies.
+    pub fn is_empty(&self) -> bool {
+        self.query.is_none() && self.document.is_none()
+    }
+
+    /// The document template, if any.
+    pub fn document_template(&self) -> Option<&str> {
+        self.document.as_deref()
+    }
+
+    /// Apply the query template.
+    pub fn query<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        apply(self.query.as_deref(), text)
+    }
+
+    /// Apply the document template.
+    pub fn document<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        apply(self.document.as_deref(), text)
+    }
+}
+
+fn apply<'a>(template: Option<&str>, text: &'a str) -> Cow<'a, str> {
+    match template {
+        Some(template) => Cow::Owned(template.replace(PLACEHOLDER, text)),
+        None => Cow::Borrowed(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates() {
+        let none = TemplateConfig::default();
+
+        let bge = Templates::for_model("bge-small-en-v1.5", &none).unwrap();
+        assert_eq!(
+            bge.query("parse json"),
+            "Represent this sentence for searching relevant passages: parse json"
+        );
+        assert_eq!(bge.document("fn parse()"), "fn parse()");
+
+        // Names of served models vary, so any name containing a known one matches
+        let nomic = Templates::for_model("nomic-ai/nomic-embed-text-v1.5", &none).unwrap();
+        assert_eq!(nomic.query("x"), "search_query: x");
+        assert_eq!(nomic.document("x"), "search_document: x");
+
+        assert!(
+            Templates::for_model("jina-embeddings-v2-base-code", &none)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_configured_templates() {
+        let config = TemplateConfig {
+            query: Some("Instruct: find code\nQuery: {text}".to_string()),
+            document: None,
+        };
+        let templates = Templates::for_model("e5-base-v2", &config).unwrap();
+        assert_eq!(templates.query("sort"), "Instruct: find code\nQuery: sort");
+        assert_eq!(templates.document("sort"), "passage: sort");
+
+        // A template without the text is a mistake
+        let config = TemplateConfig {
+            query: Some("query: ".to_string()),
+            document: None,
+        };
+        assert!(Templates::for_model("e5-base-v2", &config).is_err());
+    }
+}
@@ -1,34 +1,88 @@
 //! Text extraction from files
 //!
-//! Uses tree-sitter for intelligent code parsing when available,
-//! falls back to simple text chunking for unsupported file types.
+//! Each file is turned into chunks by the first [`Extractor`] that claims
+//! it. The built-in extractors use tree-sitter for intelligent code parsing
+//! when available, and fall back to simple text chunking for unsupported
+//! file types.
 
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod builtin;
+pub mod document;
+pub mod encoding;
+pub mod markup;
+pub mod notebook;
+#[cfg(feature = "office")]
+pub mod office;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod structured;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
 use std::path::Path;
 
-use crate::parse::{CodeLanguage, CodeParser};
+use crate::parse::{self, CodeParser};
 use crate::{Config, Result};
 
 /// Known text file extensions
 const TEXT_EXTENSIONS: &[&str] = &[
     // Plain text
-    "txt", "md", "rst", "org", "adoc", // Code
+    "txt", "md", "markdown", "rst", "org", "adoc", "asciidoc", // Code
     "rs", "py", "js", "ts", "jsx", "tsx", "go", "java", "c", "cpp", "h", "hpp", "cs", "rb", "php",
-    "swift", "kt", "scala", "hs", "ml", "ex", "exs", "erl", "clj", "cljs", "lisp", "scm", "lua",
-    "r", "jl", "nim", "zig", "v", "d", // Web
-    "html", "htm", "css", "scss", "sass", "less", "vue", "svelte", // Config
+    "rake", "gemspec", "swift", "kt", "kts", "scala", "sc", "hs", "ml", "ex", "exs", "erl", "clj",
+    "cljs", "lisp", "scm", "lua", "r", "jl", "nim", "zig", "v", "d", // Web
+    "html", "htm", "xhtml", "css", "scss", "sass", "less", "vue", "svelte", // Config
     "json", "yaml", "yml", "toml", "xml", "ini", "cfg", "conf", // Shell
     "sh", "bash", "zsh", "fish", "ps1", "bat", "cmd", // Data
-    "csv", "sql", // Docs
+    "csv", "sql", "ipynb", // Docs
     "tex", "bib",
 ];
 
+/// Prefixes of file names that are text whatever their extension (e.g. `Dockerfile.dev`)
+const TEXT_FILENAME_PREFIXES: &[&str] = &["dockerfile", "containerfile", "makefile", "gnumakefile"];
+
+/// Separates pages, slides or sheets in the text extracted from binary documents
+pub const PAGE_BREAK: char = '\x0c';
+
 /// Check if a file extension indicates a text file.
 pub fn is_text_extension(ext: &str) -> bool {
     TEXT_EXTENSIONS.contains(&ext.to_lowercase().as_str())
 }
 
-/// Check if a file should be indexed based on config and extension.
-pub fn should_index(path: &Path, config: &Config) -> bool {
+/// Check if a file extension is a binary document format with an extractor.
+fn is_document_extension(ext: &str) -> bool {
+    match ext {
+        #[cfg(feature = "pdf")]
+        "pdf" => true,
+        #[cfg(feature = "office")]
+        "docx" | "xlsx" | "pptx" | "odt" => true,
+        _ => false,
+    }
+}
+
+/// Check if a file extension is an archive or compressed file format.
+fn is_archive_extension(ext: &str) -> bool {
+    match ext {
+        #[cfg(feature = "archive")]
+        "zip" | "tar" | "tgz" | "gz" | "zst" | "tzst" => true,
+        _ => false,
+    }
+}
+
+/// Check if a file is an archive whose members should be indexed.
+pub fn is_archive(path: &Path, config: &Config) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    config.archives && is_archive_extension(&ext)
+}
+
+/// Check if a file is left out by the include and exclude lists.
+pub fn is_excluded(path: &Path, config: &Config) -> bool {
     let ext = path
         .extension()
         .and_then(|e| e.to_str())
@@ -41,32 +95,87 @@ pub fn should_index(path: &Path, config: &Config) -> bool {
         .iter()
         .any(|e| e.to_lowercase() == ext)
     {
-        return false;
+        return true;
     }
 
     // If include list is specified, only include those
-    if !config.include_extensions.is_empty() {
-        return config
+    !config.include_extensions.is_empty()
+        && !config
             .include_extensions
             .iter()
-            .any(|e| e.to_lowercase() == ext);
+            .any(|e| e.to_lowercase() == ext)
+}
+
+/// Check if a file should be indexed based on config and extension.
+pub fn should_index(path: &Path, config: &Config) -> bool {
+    if is_excluded(path, config) {
+        return false;
+    }
+    if !config.include_extensions.is_empty() {
+        return true;
     }
 
-    // Default: check if it's a known text extension
+    // Files without an extension (`Makefile`, scripts, `LICENSE`) are indexed
+    // if their content is text
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    if ext.is_empty() || TEXT_FILENAME_PREFIXES.iter().any(|p| name.starts_with(p)) {
+        return true;
+    }
+
+    // Default: check if it's a known text extension or has a runtime grammar
     is_text_extension(&ext)
+        || is_document_extension(&ext)
+        || (config.archives && is_archive_extension(&ext))
+        || config
+            .grammars
+            .iter()
+            .any(|g| g.extensions.iter().any(|e| e.to_lowercase() == ext))
 }
 
-/// Extract text content from a file.
+/// A file extracted from an archive.
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// Path of the member within the archive
+    pub name: String,
+    /// The decompressed content
+    pub content: Vec<u8>,
+}
+
+/// Extract the files in an archive, passing each to `visit` as it is read.
 ///
-/// For now, this just reads the file as UTF-8 text.
-/// Future: Add support for PDFs, Office docs, etc.
-pub fn extract_text(path: &Path) -> Result<String> {
-    let content = std::fs::read_to_string(path)?;
-    Ok(content)
+/// Without the `archive` feature, there are none.
+#[cfg(feature = "archive")]
+pub fn extract_members(
+    path: &Path,
+    config: &Config,
+    visit: impl FnMut(Member) -> Result<()>,
+) -> Result<()> {
+    archive::extract_members(path, config, visit)
+}
+
+/// Extract the files in an archive, passing each to `visit` as it is read.
+///
+/// Without the `archive` feature, there are none.
+#[cfg(not(feature = "archive"))]
+pub fn extract_members(
+    _path: &Path,
+    _config: &Config,
+    _visit: impl FnMut(Member) -> Result<()>,
+) -> Result<()> {
+    Ok(())
 }
 
 /// A chunk of text with metadata.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Chunk {
     /// The text content
     pub text: String,
@@ -76,113 +185,441 @@ pub struct Chunk {
     pub end_line: usize,
     /// Chunk index within the file
     pub index: usize,
+    /// Structural context, such as the heading breadcrumb of a document section
+    pub context: Option<String>,
+    /// Page number (1-indexed) in paged documents such as PDFs
+    pub page: Option<usize>,
+    /// Cell number (1-indexed) in Jupyter notebooks
+    pub cell: Option<usize>,
+    /// Position within a document without meaningful lines (e.g. `slide 4`)
+    pub location: Option<String>,
+    /// Extractor-specific fields, stored in the payload as is
+    pub metadata: HashMap<String, serde_json::Value>,
 }
 
-/// Extract chunks from a file using the best available method.
+impl Chunk {
+    /// The text to embed: the chunk text, prefixed by its context if any.
+    pub fn embedding_text(&self) -> Cow<'_, str> {
+        with_context(self.context.as_deref(), &self.text)
+    }
+}
+
+/// Prefix a chunk's text with its structural context, if any.
+pub(crate) fn with_context<'a>(context: Option<&str>, text: &'a str) -> Cow<'a, str> {
+    match context {
+        Some(context) => Cow::Owned(format!("{}\n\n{}", context, text)),
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// Measures the length of a piece of text in the configured chunk unit.
+pub type Measure<'a> = &'a dyn Fn(&str) -> usize;
+
+/// Turns the content of a file into chunks.
 ///
-/// For supported code languages, uses tree-sitter to extract semantic units
-/// (functions, classes, structs, etc.). Falls back to simple text chunking
-/// for unsupported languages or plain text files.
-pub fn extract_chunks(
-    path: &Path,
+/// Extractors registered with [`Indexer::add_extractor`](crate::Indexer::add_extractor)
+/// are tried before the built-in ones (see [`builtin`]), and may claim any
+/// file that is not excluded in the config, including binary formats.
+pub trait Extractor: Send {
+    /// Check if this extractor handles a file, by its path or its content.
+    fn claims(&self, path: &Path, content: &[u8]) -> bool;
+
+    /// Extract chunks from the content of a file.
+    ///
+    /// `path` is relative to the repository root; archive members have
+    /// virtual paths such as `vendor/lib.zip!/src/main.rs`.
+    fn extract(
+        &self,
+        path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>>;
+}
+
+/// What an [`Extractor`] has to work with besides the file itself.
+pub struct ExtractContext<'a> {
+    /// Repository configuration
+    pub config: &'a Config,
+    /// Measures text in the configured chunk unit
+    pub measure: Measure<'a>,
+    /// Tree-sitter parser, with runtime grammars and custom queries loaded
+    pub parser: &'a mut CodeParser,
+}
+
+impl<'a> ExtractContext<'a> {
+    /// Chunk size, overlap and measure, as taken by the chunking functions.
+    pub fn sizes(&self) -> (usize, usize, Measure<'a>) {
+        (
+            self.config.chunk_size,
+            self.config.chunk_overlap,
+            self.measure,
+        )
+    }
+}
+
+/// The extractors used to index files: registered ones, then the built-in ones.
+pub struct Extractors {
+    registered: Vec<Box<dyn Extractor>>,
+    builtin: Vec<Box<dyn Extractor>>,
+}
+
+impl Extractors {
+    /// Create a set of the built-in extractors.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            registered: Vec::new(),
+            builtin: builtin::extractors(config),
+        }
+    }
+
+    /// Register an extractor. Registered extractors are tried in order,
+    /// before the built-in ones.
+    pub fn register(&mut self, extractor: Box<dyn Extractor>) {
+        self.registered.push(extractor);
+    }
+
+    /// Check if any extractor was registered.
+    pub fn has_registered(&self) -> bool {
+        !self.registered.is_empty()
+    }
+
+    /// Find the extractor for a file: the first registered one that claims
+    /// it or, for files that [`should_index`] accepts, the first built-in one.
+    pub fn find(&self, path: &Path, content: &[u8], config: &Config) -> Option<&dyn Extractor> {
+        if is_excluded(path, config) {
+            return None;
+        }
+
+        let builtin = if should_index(path, config) {
+            &self.builtin[..]
+        } else {
+            &[]
+        };
+        self.registered
+            .iter()
+            .chain(builtin)
+            .find(|e| e.claims(path, content))
+            .map(|e| e.as_ref())
+    }
+}
+
+/// Split chunks that are much larger than the chunk size (e.g. huge functions).
+fn split_large_chunks(
+    chunks: Vec<Chunk>,
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure,
+) -> Vec<Chunk> {
+    let mut result = Vec::new();
+    for chunk in chunks {
+        if measure(&chunk.text) > chunk_size * 2 {
+            let sub_chunks = chunk_text_by(&chunk.text, chunk_size, overlap, measure);
+            for mut sub in sub_chunks {
+                // Adjust line numbers relative to parent
+                sub.start_line += chunk.start_line - 1;
+                sub.end_line += chunk.start_line - 1;
+                sub.index = result.len();
+                result.push(sub);
+            }
+        } else {
+            result.push(Chunk {
+                index: result.len(),
+                ..chunk
+            });
+        }
+    }
+    result
+}
+
+/// Chunk a single-file component (Vue, Svelte).
+///
+/// `<script>` blocks are parsed with the JavaScript or TypeScript grammar;
+/// the template and styles between them are chunked as text. Line numbers
+/// refer to the original file.
+fn chunk_component(
     text: &str,
     chunk_size: usize,
     overlap: usize,
+    measure: Measure,
     parser: &mut CodeParser,
 ) -> Vec<Chunk> {
-    // Try tree-sitter parsing for code files
-    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-        if CodeLanguage::from_extension(ext).is_some() {
-            if let Some(chunks) = parser.parse_file(path, text) {
-                // If tree-sitter extracted chunks, use them
-                // But if any chunk is too large, split it further
-                let mut result = Vec::new();
-                for chunk in chunks {
-                    if chunk.text.len() > chunk_size * 2 {
-                        // Split large chunks (e.g., huge functions)
-                        let sub_chunks = chunk_text(&chunk.text, chunk_size, overlap);
-                        for mut sub in sub_chunks {
-                            // Adjust line numbers relative to parent
-                            sub.start_line += chunk.start_line - 1;
-                            sub.end_line = sub.start_line + sub.text.matches('\n').count();
-                            sub.index = result.len();
-                            result.push(sub);
-                        }
-                    } else {
-                        result.push(Chunk {
-                            index: result.len(),
-                            ..chunk
-                        });
-                    }
-                }
-                if !result.is_empty() {
-                    return result;
-                }
-            }
+    let mut chunks = Vec::new();
+    let mut markup_start = 0;
+
+    // Shift chunks of a slice starting at byte `offset` to file line numbers
+    let push_offset = |chunks: &mut Vec<Chunk>, sub: Vec<Chunk>, offset: usize| {
+        let line_offset = text[..offset].matches('\n').count();
+        for mut chunk in sub {
+            chunk.start_line += line_offset;
+            chunk.end_line += line_offset;
+            chunks.push(chunk);
         }
+    };
+
+    for block in parse::script_blocks(text) {
+        // Markup before this script
+        let markup = &text[markup_start..block.element.start];
+        if !markup.trim().is_empty() {
+            let sub = chunk_text_by(markup, chunk_size, overlap, measure);
+            push_offset(&mut chunks, sub, markup_start);
+        }
+        markup_start = block.element.end;
+
+        let content = &text[block.content.clone()];
+        let parsed = block
+            .lang
+            .and_then(|lang| parser.parse_source(lang, content))
+            .filter(|c| !c.is_empty());
+        let sub = match parsed {
+            Some(sub) => split_large_chunks(sub, chunk_size, overlap, measure),
+            None if content.trim().is_empty() => Vec::new(),
+            // Script language not compiled in: chunk the whole element as text
+            None => {
+                let element = &text[block.element.clone()];
+                let sub = chunk_text_by(element, chunk_size, overlap, measure);
+                push_offset(&mut chunks, sub, block.element.start);
+                continue;
+            }
+        };
+        push_offset(&mut chunks, sub, block.content.start);
+    }
+
+    // Markup after the last script
+    let markup = &text[markup_start..];
+    if !markup.trim().is_empty() {
+        let sub = chunk_text_by(markup, chunk_size, overlap, measure);
+        push_offset(&mut chunks, sub, markup_start);
     }
 
-    // Fall back to simple text chunking
-    chunk_text(text, chunk_size, overlap)
+    chunks.sort_by_key(|c| c.start_line);
+    for (index, chunk) in chunks.iter_mut().enumerate() {
+        chunk.index = index;
+    }
+    chunks
 }
 
-/// Split text into chunks with overlap (fallback for non-code files).
+/// Count the characters (Unicode scalar values) in a string.
+pub fn count_chars(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Split text into chunks with overlap, measuring size in characters.
 pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<Chunk> {
-    if text.is_empty() {
-        return Vec::new();
-    }
+    chunk_text_by(text, chunk_size, overlap, &count_chars)
+}
 
+/// Split text into chunks with overlap (fallback for non-code files).
+///
+/// `chunk_size` and `overlap` are expressed in the unit counted by `measure`,
+/// e.g. characters or tokens of the embedding model. Chunks always start and
+/// end on character boundaries and prefer to break at a paragraph, then a
+/// line, then a sentence, then a word boundary.
+pub fn chunk_text_by(
+    text: &str,
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure,
+) -> Vec<Chunk> {
     let mut chunks = Vec::new();
-    let mut char_pos = 0;
-    let mut chunk_index = 0;
-
-    while char_pos < text.len() {
-        let start_pos = char_pos;
-        let end_pos = (char_pos + chunk_size).min(text.len());
-
-        // Find the actual end position (try to break at line boundary)
-        let chunk_end = if end_pos < text.len() {
-            // Look for a newline near the end
-            text[start_pos..end_pos]
-                .rfind('\n')
-                .map(|p| start_pos + p + 1)
-                .unwrap_or(end_pos)
-        } else {
-            end_pos
-        };
+    let mut start_line = 1;
+    let mut line_pos = 0;
 
-        let chunk_text = &text[start_pos..chunk_end];
+    for (index, range) in chunk_ranges(text, chunk_size, overlap, measure)
+        .into_iter()
+        .enumerate()
+    {
+        // Count lines incrementally; chunk starts only ever move forward
+        start_line += text[line_pos..range.start].matches('\n').count();
+        line_pos = range.start;
 
-        // Calculate line numbers
-        let start_line = text[..start_pos].matches('\n').count() + 1;
-        let end_line = text[..chunk_end].matches('\n').count() + 1;
+        let chunk_text = &text[range];
+        // A trailing newline ends the chunk's last line rather than starting a new one
+        let body = chunk_text.strip_suffix('\n').unwrap_or(chunk_text);
+        let end_line = start_line + body.matches('\n').count();
 
         chunks.push(Chunk {
             text: chunk_text.to_string(),
             start_line,
             end_line,
-            index: chunk_index,
+            index,
+            ..Default::default()
         });
+    }
+
+    chunks
+}
+
+/// Compute the byte ranges of the chunks `chunk_text_by` produces.
+///
+/// Ranges are non-empty, lie on character boundaries, cover the whole text
+/// and start strictly after the previous range's start.
+fn chunk_ranges(
+    text: &str,
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure,
+) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    while start < text.len() {
+        let rest = &text[start..];
+
+        // Longest prefix that fits in the chunk size, always at least one character
+        let fit = longest_fitting(
+            rest.len(),
+            chunk_size,
+            |n| rest.is_char_boundary(n),
+            |n| measure(&rest[..n]) <= chunk_size,
+        );
+        let fit = if fit == 0 {
+            rest.chars().next().map_or(0, char::len_utf8)
+        } else {
+            fit
+        };
+
+        // Unless this is the last chunk, end it at the best natural boundary
+        let end = if fit < rest.len() {
+            start + break_point(&rest[..fit])
+        } else {
+            text.len()
+        };
+        ranges.push(start..end);
 
-        // Move position forward, accounting for overlap
-        if chunk_end >= text.len() {
+        if end >= text.len() {
             break;
         }
 
-        char_pos = if overlap < chunk_end - start_pos {
-            chunk_end - overlap
+        // Longest suffix of the chunk that fits in the overlap
+        let chunk = &text[start..end];
+        let back = if overlap == 0 {
+            0
         } else {
-            chunk_end
+            longest_fitting(
+                chunk.len(),
+                overlap,
+                |n| chunk.is_char_boundary(chunk.len() - n),
+                |n| measure(&chunk[chunk.len() - n..]) <= overlap,
+            )
+        };
+
+        // Start the overlap at a line start when it spans one
+        let overlap_start = end - back;
+        let next = match text[overlap_start..end].find('\n') {
+            Some(p) if overlap_start + p + 1 < end => overlap_start + p + 1,
+            _ => overlap_start,
         };
-        chunk_index += 1;
+
+        start = if next > start { next } else { end };
     }
 
-    chunks
+    ranges
+}
+
+/// Choose where to end a chunk within `window`, as a byte offset into it.
+///
+/// Prefers the last paragraph break, then line break, then sentence end, then
+/// whitespace, as long as it keeps at least half the window. Otherwise the
+/// whole window is used.
+fn break_point(window: &str) -> usize {
+    let min = window.len() / 2;
+
+    let candidates = [
+        window.rfind("\n\n").map(|p| p + 2),
+        window.rfind('\n').map(|p| p + 1),
+        last_sentence_end(window),
+        window
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8()),
+    ];
+
+    candidates
+        .into_iter()
+        .flatten()
+        .find(|&p| p > min && p <= window.len())
+        .unwrap_or(window.len())
+}
+
+/// Byte offset just past the last sentence terminator in `text`.
+///
+/// A terminator is `.`, `!` or `?` followed by whitespace (the whitespace is
+/// included), or a full-width `。`, `！` or `？`.
+fn last_sentence_end(text: &str) -> Option<usize> {
+    let mut end = None;
+    let mut prev = None;
+
+    for (i, c) in text.char_indices() {
+        if matches!(c, '。' | '！' | '？')
+            || (c.is_whitespace() && matches!(prev, Some('.' | '!' | '?')))
+        {
+            end = Some(i + c.len_utf8());
+        }
+        prev = Some(c);
+    }
+
+    end
+}
+
+/// Find the largest `n` in `0..=max` accepted by `valid` for which `fits` holds.
+///
+/// `fits` must be monotonic (true up to some point, false after it) and is
+/// assumed to hold for zero. The search starts around `hint` and grows
+/// exponentially, so expensive measures only see slightly more text than fits.
+fn longest_fitting(
+    max: usize,
+    hint: usize,
+    valid: impl Fn(usize) -> bool,
+    fits: impl Fn(usize) -> bool,
+) -> usize {
+    if max == 0 {
+        return 0;
+    }
+
+    let snap_up = |mut n: usize| {
+        while n < max && !valid(n) {
+            n += 1;
+        }
+        n
+    };
+
+    // Grow the upper bound until it no longer fits
+    let mut lo = 0;
+    let mut hi = snap_up(hint.clamp(1, max));
+    while fits(hi) {
+        lo = hi;
+        if hi == max {
+            return max;
+        }
+        hi = snap_up(hi.saturating_mul(2).min(max));
+    }
+
+    // Binary search between a fitting `lo` and a non-fitting `hi`
+    loop {
+        let mut mid = (lo + hi) / 2;
+        while mid > lo && !valid(mid) {
+            mid -= 1;
+        }
+        if mid == lo {
+            mid = snap_up((lo + hi) / 2 + 1);
+            if mid >= hi {
+                return lo;
+            }
+        }
+
+        if fits(mid) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_is_text_extension() {
@@ -203,9 +640,207 @@ mod tests {
         assert_eq!(chunks[0].start_line, 1);
     }
 
+    #[test]
+    fn test_chunk_text_by_measure() {
+        // Count words instead of characters
+        let words = |s: &str| s.split_whitespace().count();
+        let text = "one two three\nfour five six\nseven eight nine\n";
+        let chunks = chunk_text_by(text, 6, 0, &words);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].text, "one two three\nfour five six\n");
+        assert_eq!(chunks[1].start_line, 3);
+    }
+
     #[test]
     fn test_chunk_empty() {
         let chunks = chunk_text("", 100, 10);
         assert!(chunks.is_empty());
     }
+
+    #[test]
+    fn test_chunk_multibyte() {
+        // Chunk and overlap sizes that land inside multi-byte characters
+        let text = "日本語のテキスト。🦀 émoji and accents: café, naïve.\n".repeat(20);
+        let chunks = chunk_text(&text, 7, 3);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(count_chars(&chunk.text) <= 7);
+        }
+    }
+
+    #[test]
+    fn test_chunk_prefers_paragraphs() {
+        let text = "First paragraph, first line.\nSecond line.\n\nSecond paragraph.\n";
+        let chunks = chunk_text(text, 50, 0);
+
+        assert_eq!(
+            chunks[0].text,
+            "First paragraph, first line.\nSecond line.\n\n"
+        );
+        assert_eq!(chunks[0].end_line, 3);
+        assert_eq!(chunks[1].start_line, 4);
+    }
+
+    #[test]
+    fn test_chunk_prefers_sentences() {
+        let text = "One sentence here. Another one follows. And a third";
+        let chunks = chunk_text(text, 45, 0);
+
+        assert_eq!(chunks[0].text, "One sentence here. Another one follows. ");
+        assert_eq!(chunks[1].text, "And a third");
+    }
+
+    #[cfg(feature = "typescript")]
+    #[test]
+    fn test_chunk_component() {
+        let text = r#"<template>
+  <button @click="increment">{{ count }}</button>
+</template>
+
+<script setup lang="ts">
+import { ref } from "vue";
+
+const count = ref<number>(0);
+
+function increment(): void {
+    count.value++;
+}
+</script>
+"#;
+        let mut parser = CodeParser::new();
+        let chunks = chunk_component(text, 2000, 200, &count_chars, &mut parser);
+
+        assert_eq!(chunks.len(), 3); // template, const, function
+        assert!(chunks[0].text.contains("<button"));
+        assert_eq!(chunks[1].text, "const count = ref<number>(0);");
+        assert_eq!(chunks[1].start_line, 8);
+        assert_eq!(chunks[2].start_line, 10);
+        assert_eq!(chunks[2].end_line, 12);
+    }
+
+    /// Claims files starting with a magic number, whatever their name
+    struct MagicExtractor;
+
+    impl Extractor for MagicExtractor {
+        fn claims(&self, _path: &Path, content: &[u8]) -> bool {
+            content.starts_with(b"\x00DSL")
+        }
+
+        fn extract(
+            &self,
+            _path: &Path,
+            content: &[u8],
+            _context: &mut ExtractContext,
+        ) -> Result<Vec<Chunk>> {
+            Ok(vec![Chunk {
+                text: String::from_utf8_lossy(&content[4..]).into_owned(),
+                start_line: 1,
+                end_line: 1,
+                metadata: HashMap::from([("format".to_string(), "dsl".into())]),
+                ..Default::default()
+            }])
+        }
+    }
+
+    #[test]
+    fn test_registered_extractor() {
+        let config = Config::default();
+        let mut extractors = Extractors::new(&config);
+        let path = Path::new("policy.bin");
+        let content = b"\x00DSLrule deny_all";
+        assert!(extractors.find(path, content, &config).is_none());
+
+        extractors.register(Box::new(MagicExtractor));
+        let extractor = extractors.find(path, content, &config).unwrap();
+        let mut parser = CodeParser::new();
+        let mut context = ExtractContext {
+            config: &config,
+            measure: &count_chars,
+            parser: &mut parser,
+        };
+        let chunks = extractor.extract(path, content, &mut context).unwrap();
+        assert_eq!(chunks[0].text, "rule deny_all");
+        assert_eq!(chunks[0].metadata["format"], "dsl");
+
+        // Built-in extractors still handle the rest, but not binary content
+        assert!(
+            extractors
+                .find(Path::new("notes.txt"), b"hi", &config)
+                .is_some()
+        );
+        assert!(
+            extractors
+                .find(Path::new("notes.txt"), b"\x00\x01", &config)
+                .is_none()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_chunks_cover_text(
+            text in "\\PC*(\n\\PC*){0,8}",
+            chunk_size in 1usize..64,
+            overlap in 0usize..32,
+        ) {
+            let ranges = chunk_ranges(&text, chunk_size, overlap, &count_chars);
+
+            if text.is_empty() {
+                prop_assert!(ranges.is_empty());
+            } else {
+                prop_assert_eq!(ranges[0].start, 0);
+                prop_assert_eq!(ranges[ranges.len() - 1].end, text.len());
+            }
+
+            for (i, range) in ranges.iter().enumerate() {
+                prop_assert!(range.start < range.end);
+                prop_assert!(text.is_char_boundary(range.start));
+                prop_assert!(text.is_char_boundary(range.end));
+
+                // Within the size limit, unless a single character is larger
+                let len = count_chars(&text[range.clone()]);
+                prop_assert!(len <= chunk_size.max(1));
+
+                // No gaps, and always making progress
+                if i > 0 {
+                    prop_assert!(range.start > ranges[i - 1].start);
+                    prop_assert!(range.start <= ranges[i - 1].end);
+                }
+            }
+        }
+
+        #[test]
+        fn prop_chunk_line_numbers(
+            text in any::<String>(),
+            chunk_size in 1usize..64,
+            overlap in 0usize..32,
+        ) {
+            let chunks = chunk_text(&text, chunk_size, overlap);
+            let ranges = chunk_ranges(&text, chunk_size, overlap, &count_chars);
+            prop_assert_eq!(chunks.len(), ranges.len());
+
+            for (chunk, range) in chunks.iter().zip(ranges) {
+                prop_assert_eq!(&chunk.text, &text[range.clone()]);
+                prop_assert_eq!(chunk.start_line, text[..range.start].matches('\n').count() + 1);
+                prop_assert!(chunk.end_line >= chunk.start_line);
+            }
+        }
+
+        #[test]
+        fn prop_chunk_by_bytes_never_splits_chars(
+            text in any::<String>(),
+            chunk_size in 1usize..64,
+            overlap in 0usize..32,
+        ) {
+            // Byte-based measures hit mid-character offsets constantly
+            let ranges = chunk_ranges(&text, chunk_size, overlap, &str::len);
+
+            for range in ranges {
+                prop_assert!(text.is_char_boundary(range.start));
+                prop_assert!(text.is_char_boundary(range.end));
+                prop_assert!(range.len() <= chunk_size.max(4));
+            }
+        }
+    }
 }
@@ -0,0 +1,279 @@
+//! Archive extraction: the members of zip and tar archives, and
+//! single gzip or zstd compressed files
+//!
+//! Members are read in memory one at a time, never written to disk. Each
+//! member is indexed under a virtual path such as `vendor/lib.zip!/src/main.rs`.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+
+use super::{Member, is_archive_extension, is_document_extension, is_excluded};
+use crate::{Config, QsError, Result};
+
+/// Supported archive and compression formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+    /// A single gzip compressed file
+    Gzip,
+    /// A single zstd compressed file
+    Zstd,
+}
+
+impl ArchiveFormat {
+    /// Detect the format from a file name, including compound extensions
+    /// such as `.tar.gz`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        let format = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Self::TarGz
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Self::TarZst
+        } else if name.ends_with(".tar") {
+            Self::Tar
+        } else if name.ends_with(".zip") {
+            Self::Zip
+        } else if name.ends_with(".gz") {
+            Self::Gzip
+        } else if name.ends_with(".zst") {
+            Self::Zstd
+        } else {
+            return None;
+        };
+        Some(format)
+    }
+}
+
+/// Extract the files in an archive, passing each to `visit` as it is read.
+///
+/// Members larger than `max_file_size` once decompressed are skipped, as are
+/// documents, nested archives and excluded extensions. Whether the rest is
+/// indexed is up to the extractors, as for any other file. Errors from
+/// `visit` stop the extraction and are returned as they are.
+pub fn extract_members(
+    path: &Path,
+    config: &Config,
+    mut visit: impl FnMut(Member) -> Result<()>,
+) -> Result<()> {
+    let format = ArchiveFormat::from_path(path)
+        .ok_or_else(|| QsError::Extract(format!("{}: not an archive", path.display())))?;
+    let reader = BufReader::new(File::open(path)?);
+    let visit: &mut Visit = &mut visit;
+
+    let read = match format {
+        ArchiveFormat::Zip => read_zip(reader, config, visit),
+        ArchiveFormat::Tar => read_tar(reader, config, visit),
+        ArchiveFormat::TarGz => read_tar(MultiGzDecoder::new(reader), config, visit),
+        ArchiveFormat::TarZst => read_tar(zstd::Decoder::with_buffer(reader)?, config, visit),
+        ArchiveFormat::Gzip => read_single(path, MultiGzDecoder::new(reader), config, visit),
+        ArchiveFormat::Zstd => {
+            read_single(path, zstd::Decoder::with_buffer(reader)?, config, visit)
+        }
+    };
+    read.map_err(|e| match e.downcast::<QsError>() {
+        Ok(e) => *e,
+        Err(e) => QsError::Extract(format!("{}: {}", path.display(), e)),
+    })
+}
+
+type ReadResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+type Visit<'a> = dyn FnMut(Member) -> Result<()> + 'a;
+
+fn read_zip(
+    reader: impl Read + std::io::Seek,
+    config: &Config,
+    visit: &mut Visit,
+) -> ReadResult<()> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        let name = file.name().to_string();
+        if !file.is_file()
+            || file.size() > config.max_file_size
+            || !is_indexed_member(&name, config)
+        {
+            continue;
+        }
+        if let Some(member) = read_member(name, file, config)? {
+            visit(member)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_tar(reader: impl Read, config: &Config, visit: &mut Visit) -> ReadResult<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if !entry.header().entry_type().is_file()
+            || entry.size() > config.max_file_size
+            || !is_indexed_member(&name, config)
+        {
+            continue;
+        }
+        if let Some(member) = read_member(name, entry, config)? {
+            visit(member)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a single compressed file, named after the file without its
+/// compression extension.
+fn read_single(
+    path: &Path,
+    reader: impl Read,
+    config: &Config,
+    visit: &mut Visit,
+) -> ReadResult<()> {
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Some(member) = read_member(name, reader, config)? {
+        visit(member)?;
+    }
+    Ok(())
+}
+
+/// Read a member's content, or `None` if it is too large or empty.
+///
+/// Sizes in archive headers can lie, so reading also stops past the limit.
+fn read_member(name: String, reader: impl Read, config: &Config) -> ReadResult<Option<Member>> {
+    let mut content = Vec::new();
+    reader
+        .take(config.max_file_size + 1)
+        .read_to_end(&mut content)?;
+    if content.is_empty() || content.len() as u64 > config.max_file_size {
+        return Ok(None);
+    }
+
+    Ok(Some(Member { name, content }))
+}
+
+/// Whether an archive member may be indexed: documents, nested archives and
+/// excluded extensions are skipped.
+fn is_indexed_member(name: &str, config: &Config) -> bool {
+    let path = Path::new(name);
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    !is_excluded(path, config) && !is_document_extension(&ext) && !is_archive_extension(&ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn config() -> Config {
+        Config {
+            archives: true,
+            max_file_size: 64,
+            ..Config::default()
+        }
+    }
+
+    fn names(members: &[Member]) -> Vec<&str> {
+        members.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    /// Collect the members a reader visits.
+    fn collect(read: impl FnOnce(&mut Visit) -> ReadResult<()>) -> Vec<Member> {
+        let mut members = Vec::new();
+        read(&mut |member| {
+            members.push(member);
+            Ok(())
+        })
+        .unwrap();
+        members
+    }
+
+    #[test]
+    fn test_archive_format() {
+        let format = |name: &str| ArchiveFormat::from_path(Path::new(name));
+        assert_eq!(format("vendor/lib.tar.gz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(format("lib.TGZ"), Some(ArchiveFormat::TarGz));
+        assert_eq!(format("release.zip"), Some(ArchiveFormat::Zip));
+        assert_eq!(format("app.log.gz"), Some(ArchiveFormat::Gzip));
+        assert_eq!(format("dump.sql.zst"), Some(ArchiveFormat::Zstd));
+        assert_eq!(format("main.rs"), None);
+    }
+
+    #[test]
+    fn test_read_zip() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        let files: &[(&str, &[u8])] = &[
+            ("src/main.rs", b"fn main() {}\n"),
+            ("src/big.rs", &[b'x'; 100]),
+            ("vendor/nested.zip", b"PK\x05\x06"),
+            ("empty.txt", b""),
+        ];
+        zip.add_directory("src/", options).unwrap();
+        for (name, content) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let members = collect(|visit| read_zip(Cursor::new(bytes), &config(), visit));
+        assert_eq!(names(&members), ["src/main.rs"]);
+        assert_eq!(members[0].content, b"fn main() {}\n");
+    }
+
+    #[test]
+    fn test_read_tar_gz() {
+        let mut tar = tar::Builder::new(Vec::new());
+        for (name, content) in [
+            ("lib/util.py", "def util():\n    pass\n"),
+            ("README.md", "# lib\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            tar.append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        let tar = tar.into_inner().unwrap();
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&tar).unwrap();
+        let bytes = gz.finish().unwrap();
+
+        let members = collect(|visit| read_tar(MultiGzDecoder::new(&bytes[..]), &config(), visit));
+        assert_eq!(names(&members), ["lib/util.py", "README.md"]);
+    }
+
+    #[test]
+    fn test_read_single() {
+        let log = "GET /health 200\nGET /login 500\n";
+        let bytes = zstd::encode_all(log.as_bytes(), 0).unwrap();
+        let path = Path::new("logs/app.log.zst");
+
+        let decoder = zstd::Decoder::new(&bytes[..]).unwrap();
+        let members = collect(|visit| read_single(path, decoder, &config(), visit));
+        assert_eq!(names(&members), ["app.log"]);
+        assert_eq!(members[0].content, log.as_bytes());
+
+        // Decompressed size is checked against the limit
+        let big = zstd::encode_all(&[b'a'; 1000][..], 0).unwrap();
+        let decoder = zstd::Decoder::new(&big[..]).unwrap();
+        assert!(collect(|visit| read_single(path, decoder, &config(), visit)).is_empty());
+    }
+}
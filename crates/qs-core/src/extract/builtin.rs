@@ -0,0 +1,282 @@
+//! Built-in extractors
+//!
+//! These are tried in order after any registered extractor, and only for
+//! files that [`should_index`](super::should_index) accepts. All of them but
+//! the PDF and office extractors skip binary content.
+
+use std::path::Path;
+
+use super::document::{self, DocumentFormat};
+use super::markup::{self, MarkupFormat};
+use super::structured::{self, StructuredFormat};
+use super::{
+    Chunk, ExtractContext, Extractor, chunk_component, chunk_text_by, encoding, notebook,
+    split_large_chunks,
+};
+use crate::Result;
+use crate::parse::{self, CodeLanguage};
+
+/// The built-in extractors, in the order they are tried.
+pub fn extractors(config: &crate::Config) -> Vec<Box<dyn Extractor>> {
+    let grammar_extensions = config
+        .grammars
+        .iter()
+        .flat_map(|g| g.extensions.iter().map(|e| e.to_lowercase()))
+        .collect();
+
+    vec![
+        Box::new(CodeExtractor { grammar_extensions }),
+        Box::new(NotebookExtractor),
+        #[cfg(feature = "pdf")]
+        Box::new(PdfExtractor),
+        #[cfg(feature = "office")]
+        Box::new(OfficeExtractor),
+        Box::new(ComponentExtractor),
+        Box::new(MarkupExtractor),
+        Box::new(DocumentExtractor),
+        Box::new(StructuredExtractor),
+        Box::new(TextExtractor),
+    ]
+}
+
+/// Lowercase extension of a path, or an empty string.
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+fn is_text(content: &[u8]) -> bool {
+    !encoding::is_binary(content)
+}
+
+/// Source code, parsed with tree-sitter into functions, classes and the like.
+///
+/// The language comes from the extension, or for files without one, the file
+/// name or shebang line. Extensions of runtime grammars are claimed too.
+pub struct CodeExtractor {
+    grammar_extensions: Vec<String>,
+}
+
+impl Extractor for CodeExtractor {
+    fn claims(&self, path: &Path, content: &[u8]) -> bool {
+        let head = String::from_utf8_lossy(&content[..content.len().min(256)]);
+        (self.grammar_extensions.contains(&extension(path))
+            || CodeLanguage::detect(path, &head).is_some())
+            && is_text(content)
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>> {
+        let text = encoding::decode(content);
+        let (size, overlap, measure) = context.sizes();
+
+        // Split chunks that are too large (e.g. huge functions), and fall
+        // back to text chunking if parsing fails
+        let chunks = match context.parser.parse_file(path, &text) {
+            Some(chunks) => split_large_chunks(chunks, size, overlap, measure),
+            None => chunk_text_by(&text, size, overlap, measure),
+        };
+        Ok(chunks)
+    }
+}
+
+/// Jupyter notebooks, cell by cell.
+pub struct NotebookExtractor;
+
+impl Extractor for NotebookExtractor {
+    fn claims(&self, path: &Path, content: &[u8]) -> bool {
+        notebook::is_notebook(path) && is_text(content)
+    }
+
+    fn extract(
+        &self,
+        _path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>> {
+        let text =
+            notebook::text_from_json(&encoding::decode(content), context.config.notebook_outputs)?;
+        let (size, overlap, measure) = context.sizes();
+        Ok(notebook::chunk_notebook(
+            &text,
+            size,
+            overlap,
+            measure,
+            context.parser,
+        ))
+    }
+}
+
+/// PDFs, page by page.
+#[cfg(feature = "pdf")]
+pub struct PdfExtractor;
+
+#[cfg(feature = "pdf")]
+impl Extractor for PdfExtractor {
+    fn claims(&self, path: &Path, _content: &[u8]) -> bool {
+        super::pdf::is_pdf(path)
+    }
+
+    fn extract(
+        &self,
+        _path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>> {
+        let text = super::pdf::extract_text(content)?;
+        let (size, overlap, measure) = context.sizes();
+        Ok(super::pdf::chunk_pages(&text, size, overlap, measure))
+    }
+}
+
+/// Office documents, along their paragraphs, sheets or slides.
+#[cfg(feature = "office")]
+pub struct OfficeExtractor;
+
+#[cfg(feature = "office")]
+impl Extractor for OfficeExtractor {
+    fn claims(&self, path: &Path, _content: &[u8]) -> bool {
+        super::office::OfficeFormat::from_path(path).is_some()
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>> {
+        let Some(format) = super::office::OfficeFormat::from_path(path) else {
+            return Ok(Vec::new());
+        };
+        let text = super::office::extract_text(content, format, context.config.max_file_size)?;
+        let (size, overlap, measure) = context.sizes();
+        Ok(super::office::chunk_office(
+            &text, format, size, overlap, measure,
+        ))
+    }
+}
+
+/// Single-file components (Vue, Svelte): scripts are parsed, the markup
+/// around them is chunked as text.
+pub struct ComponentExtractor;
+
+impl Extractor for ComponentExtractor {
+    fn claims(&self, path: &Path, content: &[u8]) -> bool {
+        parse::is_component_extension(&extension(path)) && is_text(content)
+    }
+
+    fn extract(
+        &self,
+        _path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>> {
+        let text = encoding::decode(content);
+        let (size, overlap, measure) = context.sizes();
+        Ok(chunk_component(
+            &text,
+            size,
+            overlap,
+            measure,
+            context.parser,
+        ))
+    }
+}
+
+/// HTML and XML, stripped to clean text.
+pub struct MarkupExtractor;
+
+impl Extractor for MarkupExtractor {
+    fn claims(&self, path: &Path, content: &[u8]) -> bool {
+        MarkupFormat::from_extension(&extension(path)).is_some() && is_text(content)
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>> {
+        let Some(format) = MarkupFormat::from_extension(&extension(path)) else {
+            return Ok(Vec::new());
+        };
+        let text = encoding::decode(content);
+        let (size, overlap, measure) = context.sizes();
+        Ok(markup::chunk_markup(&text, format, size, overlap, measure))
+    }
+}
+
+/// Prose documents (Markdown, rST, AsciiDoc, Org), split along their headings.
+pub struct DocumentExtractor;
+
+impl Extractor for DocumentExtractor {
+    fn claims(&self, path: &Path, content: &[u8]) -> bool {
+        DocumentFormat::from_extension(&extension(path)).is_some() && is_text(content)
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>> {
+        let Some(format) = DocumentFormat::from_extension(&extension(path)) else {
+            return Ok(Vec::new());
+        };
+        let text = encoding::decode(content);
+        let (size, overlap, measure) = context.sizes();
+        Ok(document::chunk_document(
+            &text, format, size, overlap, measure,
+        ))
+    }
+}
+
+/// JSON, YAML and TOML, split by key. Falls back to text chunking when the
+/// file does not parse.
+pub struct StructuredExtractor;
+
+impl Extractor for StructuredExtractor {
+    fn claims(&self, path: &Path, content: &[u8]) -> bool {
+        StructuredFormat::from_extension(&extension(path)).is_some() && is_text(content)
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>> {
+        let text = encoding::decode(content);
+        let (size, overlap, measure) = context.sizes();
+        let chunks = StructuredFormat::from_extension(&extension(path))
+            .and_then(|format| structured::chunk_structured(&text, format, size, overlap, measure))
+            .unwrap_or_else(|| chunk_text_by(&text, size, overlap, measure));
+        Ok(chunks)
+    }
+}
+
+/// Any other text, in overlapping chunks.
+pub struct TextExtractor;
+
+impl Extractor for TextExtractor {
+    fn claims(&self, _path: &Path, content: &[u8]) -> bool {
+        is_text(content)
+    }
+
+    fn extract(
+        &self,
+        _path: &Path,
+        content: &[u8],
+        context: &mut ExtractContext,
+    ) -> Result<Vec<Chunk>> {
+        let text = encoding::decode(content);
+        let (size, overlap, measure) = context.sizes();
+        Ok(chunk_text_by(&text, size, overlap, measure))
+    }
+}
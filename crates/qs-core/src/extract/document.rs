@@ -0,0 +1,684 @@
+//! Structure-aware chunking for prose documents
+//!
+//! Splits Markdown, reStructuredText, AsciiDoc and Org files at headings,
+//! keeps code blocks intact where they fit, and records the heading
+//! breadcrumb (e.g. `Installation > Linux`) of every chunk.
+
+use super::{Chunk, Measure, chunk_text_by};
+
+/// Supported document markup formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    Markdown,
+    ReStructuredText,
+    AsciiDoc,
+    Org,
+}
+
+impl DocumentFormat {
+    /// Detect document format from file extension.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "md" | "markdown" => Some(Self::Markdown),
+            "rst" => Some(Self::ReStructuredText),
+            "adoc" | "asciidoc" => Some(Self::AsciiDoc),
+            "org" => Some(Self::Org),
+            _ => None,
+        }
+    }
+}
+
+/// A line of the source document.
+struct Line<'a> {
+    /// Line content without the line terminator
+    text: &'a str,
+    /// Byte offset of the line start
+    start: usize,
+    /// Byte offset past the line terminator
+    end: usize,
+}
+
+impl Line<'_> {
+    fn is_blank(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+}
+
+/// Kind of a structural block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BlockKind {
+    /// A section heading, possibly spanning over/underline lines
+    Heading { level: usize, title: String },
+    /// A fenced or delimited code block, kept intact when possible
+    Code,
+    /// A paragraph or other run of prose
+    Text,
+}
+
+/// A run of lines forming one structural unit.
+#[derive(Debug, Clone)]
+struct Block {
+    kind: BlockKind,
+    /// First line index (0-indexed)
+    first: usize,
+    /// Last line index (0-indexed, inclusive)
+    last: usize,
+}
+
+/// Collects blocks while scanning lines, grouping prose into paragraphs.
+#[derive(Default)]
+struct Blocks {
+    blocks: Vec<Block>,
+    /// Whether the previous line was blank (ends the current paragraph)
+    after_blank: bool,
+}
+
+impl Blocks {
+    fn push(&mut self, kind: BlockKind, first: usize, last: usize) {
+        self.blocks.push(Block { kind, first, last });
+        self.after_blank = false;
+    }
+
+    /// Add a prose or blank line.
+    fn line(&mut self, index: usize, blank: bool) {
+        if blank {
+            // Blank lines trail whatever block precedes them
+            if let Some(block) = self.blocks.last_mut() {
+                block.last = index;
+            }
+            self.after_blank = true;
+            return;
+        }
+
+        match self.blocks.last_mut() {
+            Some(block) if block.kind == BlockKind::Text && !self.after_blank => {
+                block.last = index;
+            }
+            _ => self.push(BlockKind::Text, index, index),
+        }
+        self.after_blank = false;
+    }
+}
+
+/// Split a document into chunks along its heading structure.
+///
+/// Each section becomes one or more chunks. Paragraphs and code blocks are
+/// packed into chunks of at most `chunk_size` (in the unit counted by
+/// `measure`); only blocks that are larger than a chunk on their own are
+/// split further, with `overlap`.
+pub fn chunk_document(
+    text: &str,
+    format: DocumentFormat,
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure,
+) -> Vec<Chunk> {
+    let lines = split_lines(text);
+    let blocks = match format {
+        DocumentFormat::Markdown => scan_markdown(&lines),
+        DocumentFormat::ReStructuredText => scan_rst(&lines),
+        DocumentFormat::AsciiDoc => scan_asciidoc(&lines),
+        DocumentFormat::Org => scan_org(&lines),
+    };
+
+    let mut chunks = Vec::new();
+    let mut breadcrumb: Vec<(usize, String)> = Vec::new();
+    let mut section_start = 0;
+
+    while section_start < blocks.len() {
+        // A section runs from a heading (or the document start) to the next heading
+        let section_end = blocks[section_start + 1..]
+            .iter()
+            .position(|b| matches!(b.kind, BlockKind::Heading { .. }))
+            .map_or(blocks.len(), |p| section_start + 1 + p);
+        let section = &blocks[section_start..section_end];
+        section_start = section_end;
+
+        if let BlockKind::Heading { level, title } = &section[0].kind {
+            while breadcrumb.last().is_some_and(|(l, _)| l >= level) {
+                breadcrumb.pop();
+            }
+            breadcrumb.push((*level, title.clone()));
+
+            // A bare heading directly above a subsection lives on in its breadcrumb
+            let next_level = match blocks.get(section_end).map(|b| &b.kind) {
+                Some(BlockKind::Heading { level, .. }) => Some(*level),
+                _ => None,
+            };
+            if section.len() == 1 && next_level.is_some_and(|next| next > *level) {
+                continue;
+            }
+        }
+
+        let context = if breadcrumb.is_empty() {
+            None
+        } else {
+            Some(
+                breadcrumb
+                    .iter()
+                    .map(|(_, title)| title.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" > "),
+            )
+        };
+
+        pack_section(
+            text,
+            &lines,
+            section,
+            chunk_size,
+            overlap,
+            measure,
+            &context,
+            &mut chunks,
+        );
+    }
+
+    chunks
+}
+
+/// Pack the blocks of one section into chunks.
+#[allow(clippy::too_many_arguments)]
+fn pack_section(
+    text: &str,
+    lines: &[Line],
+    section: &[Block],
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure,
+    context: &Option<String>,
+    chunks: &mut Vec<Chunk>,
+) {
+    let mut current: Option<(usize, usize)> = None;
+
+    for block in section {
+        if let Some((first, last)) = current {
+            let candidate = &text[lines[first].start..lines[block.last].end];
+            if measure(candidate) <= chunk_size {
+                current = Some((first, block.last));
+                continue;
+            }
+            push_lines(text, lines, first, last, context, chunks);
+            current = None;
+        }
+
+        let block_text = &text[lines[block.first].start..lines[block.last].end];
+        if measure(block_text) <= chunk_size {
+            current = Some((block.first, block.last));
+            continue;
+        }
+
+        // A single block larger than a chunk has to be split
+        for mut sub in chunk_text_by(block_text, chunk_size, overlap, measure) {
+            sub.start_line += block.first;
+            sub.end_line += block.first;
+            sub.index = chunks.len();
+            sub.context = context.clone();
+            chunks.push(sub);
+        }
+    }
+
+    if let Some((first, last)) = current {
+        push_lines(text, lines, first, last, context, chunks);
+    }
+}
+
+/// Check if a line of plain text would be read as Markdown structure (a
+/// heading, code fence, setext underline or front matter delimiter), so a
+/// converter must escape it with a backslash.
+pub fn needs_escape(line: &str) -> bool {
+    ["#", "```", "~~~", "---", "===", "+++"]
+        .iter()
+        .any(|marker| line.starts_with(marker))
+}
+
+/// Emit the lines `first..=last` as a chunk, dropping trailing blank lines.
+fn push_lines(
+    text: &str,
+    lines: &[Line],
+    first: usize,
+    mut last: usize,
+    context: &Option<String>,
+    chunks: &mut Vec<Chunk>,
+) {
+    while last > first && lines[last].is_blank() {
+        last -= 1;
+    }
+    if lines[first..=last].iter().all(Line::is_blank) {
+        return;
+    }
+
+    chunks.push(Chunk {
+        text: text[lines[first].start..lines[last].end].to_string(),
+        start_line: first + 1,
+        end_line: last + 1,
+        index: chunks.len(),
+        context: context.clone(),
+        ..Default::default()
+    });
+}
+
+/// Split text into lines, keeping byte offsets.
+fn split_lines(text: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    for segment in text.split_inclusive('\n') {
+        let end = start + segment.len();
+        let content = segment.strip_suffix('\n').unwrap_or(segment);
+        lines.push(Line {
+            text: content.strip_suffix('\r').unwrap_or(content),
+            start,
+            end,
+        });
+        start = end;
+    }
+
+    lines
+}
+
+/// Scan Markdown: ATX and setext headings, fenced code and front matter.
+fn scan_markdown(lines: &[Line]) -> Vec<Block> {
+    let mut blocks = Blocks::default();
+    let mut i = 0;
+
+    // YAML/TOML front matter is kept as one block
+    let front_matter = lines.first().and_then(|first| {
+        let delimiter = first.text.trim_end();
+        if delimiter != "---" && delimiter != "+++" {
+            return None;
+        }
+        lines[1..]
+            .iter()
+            .position(|l| l.text.trim_end() == delimiter)
+    });
+    if let Some(close) = front_matter {
+        blocks.push(BlockKind::Code, 0, close + 1);
+        i = close + 2;
+    }
+
+    while i < lines.len() {
+        let line = lines[i].text;
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim_start();
+
+        if indent <= 3 {
+            // Fenced code block
+            if let Some((fence_char, fence_len)) = code_fence(trimmed) {
+                let close = lines[i + 1..].iter().position(|l| {
+                    let t = l.text.trim();
+                    t.len() >= fence_len && t.chars().all(|c| c == fence_char)
+                });
+                let last = close.map_or(lines.len() - 1, |p| i + 1 + p);
+                blocks.push(BlockKind::Code, i, last);
+                i = last + 1;
+                continue;
+            }
+
+            // ATX heading
+            let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+            if (1..=6).contains(&hashes)
+                && trimmed[hashes..]
+                    .chars()
+                    .next()
+                    .is_none_or(char::is_whitespace)
+            {
+                let title = trimmed[hashes..].trim().trim_end_matches('#').trim();
+                blocks.push(
+                    BlockKind::Heading {
+                        level: hashes,
+                        title: title.to_string(),
+                    },
+                    i,
+                    i,
+                );
+                i += 1;
+                continue;
+            }
+        }
+
+        // Setext heading: a text line underlined with `=` or `-`
+        let setext = if lines[i].is_blank() {
+            None
+        } else {
+            lines.get(i + 1).and_then(|next| setext_level(next.text))
+        };
+        if let Some(level) = setext {
+            blocks.push(
+                BlockKind::Heading {
+                    level,
+                    title: line.trim().to_string(),
+                },
+                i,
+                i + 1,
+            );
+            i += 2;
+            continue;
+        }
+
+        blocks.line(i, lines[i].is_blank());
+        i += 1;
+    }
+
+    blocks.blocks
+}
+
+/// Return the heading level if the line is a setext underline.
+fn setext_level(line: &str) -> Option<usize> {
+    let indent = line.len() - line.trim_start().len();
+    let underline = line.trim();
+    if underline.is_empty() || indent > 3 {
+        None
+    } else if underline.chars().all(|c| c == '=') {
+        Some(1)
+    } else if underline.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Return the fence character and length if the line opens a code fence.
+fn code_fence(trimmed: &str) -> Option<(char, usize)> {
+    let fence_char = trimmed.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let len = trimmed.chars().take_while(|&c| c == fence_char).count();
+    (len >= 3).then_some((fence_char, len))
+}
+
+/// Scan reStructuredText: adorned section titles and literal blocks.
+fn scan_rst(lines: &[Line]) -> Vec<Block> {
+    let mut blocks = Blocks::default();
+    // Heading levels follow the order in which adornment styles first appear
+    let mut styles: Vec<(char, bool)> = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i].text;
+
+        // Title with overline and underline
+        if let (Some(over), Some(title), Some(under)) =
+            (rst_adornment(line), lines.get(i + 1), lines.get(i + 2))
+        {
+            let title_text = title.text.trim();
+            if !title_text.is_empty() && rst_adornment(under.text) == Some(over) {
+                let level = rst_level(&mut styles, (over, true));
+                blocks.push(
+                    BlockKind::Heading {
+                        level,
+                        title: title_text.to_string(),
+                    },
+                    i,
+                    i + 2,
+                );
+                i += 3;
+                continue;
+            }
+        }
+
+        // Title with underline only
+        let underline = if lines[i].is_blank() || line.starts_with(char::is_whitespace) {
+            None
+        } else {
+            lines.get(i + 1).and_then(|under| {
+                let c = rst_adornment(under.text)?;
+                let long_enough =
+                    under.text.trim_end().chars().count() >= line.trim().chars().count();
+                long_enough.then_some(c)
+            })
+        };
+        if let Some(c) = underline {
+            let level = rst_level(&mut styles, (c, false));
+            blocks.push(
+                BlockKind::Heading {
+                    level,
+                    title: line.trim().to_string(),
+                },
+                i,
+                i + 1,
+            );
+            i += 2;
+            continue;
+        }
+
+        // Literal blocks (`::`) and directives such as `.. code-block::`
+        if line.trim_end().ends_with("::") {
+            let indent = line.len() - line.trim_start().len();
+            let body = lines[i + 1..]
+                .iter()
+                .take_while(|l| l.is_blank() || l.text.len() - l.text.trim_start().len() > indent)
+                .count();
+            if body > 0 {
+                let is_directive = line.trim_start().starts_with("..");
+                let first = if is_directive {
+                    i
+                } else {
+                    blocks.line(i, false);
+                    i + 1
+                };
+                blocks.push(BlockKind::Code, first, i + body);
+                i += body + 1;
+                continue;
+            }
+        }
+
+        blocks.line(i, lines[i].is_blank());
+        i += 1;
+    }
+
+    blocks.blocks
+}
+
+/// Return the adornment character if the line is an rST section adornment.
+fn rst_adornment(line: &str) -> Option<char> {
+    let line = line.trim_end();
+    let c = line.chars().next()?;
+    (c.is_ascii_punctuation() && line.len() >= 2 && line.chars().all(|x| x == c)).then_some(c)
+}
+
+/// Map an adornment style to its heading level (1-indexed).
+fn rst_level(styles: &mut Vec<(char, bool)>, style: (char, bool)) -> usize {
+    match styles.iter().position(|&s| s == style) {
+        Some(p) => p + 1,
+        None => {
+            styles.push(style);
+            styles.len()
+        }
+    }
+}
+
+/// Scan AsciiDoc: `=` headings and delimited blocks.
+fn scan_asciidoc(lines: &[Line]) -> Vec<Block> {
+    let mut blocks = Blocks::default();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i].text.trim_end();
+
+        // Delimited listing, literal, passthrough, example and sidebar blocks
+        let is_delimiter = line.len() >= 4
+            && line.starts_with(['-', '.', '+', '=', '*', '_'])
+            && line.chars().all(|c| line.starts_with(c));
+        if is_delimiter || line.starts_with("```") {
+            let close = lines[i + 1..]
+                .iter()
+                .position(|l| l.text.trim_end() == line);
+            let last = close.map_or(lines.len() - 1, |p| i + 1 + p);
+            blocks.push(BlockKind::Code, i, last);
+            i = last + 1;
+            continue;
+        }
+
+        let equals = line.chars().take_while(|&c| c == '=').count();
+        if (1..=6).contains(&equals) && line[equals..].starts_with(' ') {
+            blocks.push(
+                BlockKind::Heading {
+                    level: equals,
+                    title: line[equals..].trim().to_string(),
+                },
+                i,
+                i,
+            );
+            i += 1;
+            continue;
+        }
+
+        blocks.line(i, lines[i].is_blank());
+        i += 1;
+    }
+
+    blocks.blocks
+}
+
+/// Scan Org: `*` headlines and `#+begin_...` blocks.
+fn scan_org(lines: &[Line]) -> Vec<Block> {
+    let mut blocks = Blocks::default();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i].text;
+        let lower = line.trim_start().to_lowercase();
+
+        if let Some(name) = lower.strip_prefix("#+begin_") {
+            let name = name.split_whitespace().next().unwrap_or_default();
+            let end = format!("#+end_{}", name);
+            let close = lines[i + 1..]
+                .iter()
+                .position(|l| l.text.trim().to_lowercase() == end);
+            let last = close.map_or(lines.len() - 1, |p| i + 1 + p);
+            blocks.push(BlockKind::Code, i, last);
+            i = last + 1;
+            continue;
+        }
+
+        let stars = line.chars().take_while(|&c| c == '*').count();
+        if stars > 0 && line[stars..].starts_with(' ') {
+            blocks.push(
+                BlockKind::Heading {
+                    level: stars,
+                    title: line[stars..].trim().to_string(),
+                },
+                i,
+                i,
+            );
+            i += 1;
+            continue;
+        }
+
+        blocks.line(i, lines[i].is_blank());
+        i += 1;
+    }
+
+    blocks.blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::count_chars;
+
+    fn chunk(text: &str, format: DocumentFormat) -> Vec<Chunk> {
+        chunk_document(text, format, 2000, 200, &count_chars)
+    }
+
+    #[test]
+    fn test_markdown_breadcrumbs() {
+        let text = "\
+# Project
+
+Intro text.
+
+## Installation
+
+### Linux
+
+Run the installer.
+
+## Usage
+Setext Title
+============
+
+Body.
+";
+        let chunks = chunk(text, DocumentFormat::Markdown);
+        let contexts: Vec<_> = chunks.iter().map(|c| c.context.as_deref()).collect();
+
+        assert_eq!(
+            contexts,
+            vec![
+                Some("Project"),
+                Some("Project > Installation > Linux"),
+                Some("Project > Usage"),
+                Some("Setext Title"),
+            ]
+        );
+        assert_eq!(chunks[1].start_line, 7);
+        assert_eq!(chunks[1].end_line, 9);
+    }
+
+    #[test]
+    fn test_markdown_code_fence_intact() {
+        let text = "\
+## Config
+
+```bash
+# not a heading
+export QS_MODEL=jina
+
+# still not a heading
+```
+
+Done.
+";
+        let chunks = chunk_document(text, DocumentFormat::Markdown, 80, 0, &count_chars);
+
+        assert!(
+            chunks
+                .iter()
+                .all(|c| c.context.as_deref() == Some("Config"))
+        );
+        let fence = chunks
+            .iter()
+            .find(|c| c.text.contains("```bash"))
+            .expect("fence chunk");
+        assert!(fence.text.contains("# still not a heading\n```"));
+    }
+
+    #[test]
+    fn test_rst_headings() {
+        let text = "\
+=====
+Guide
+=====
+
+Setup
+-----
+
+Install it::
+
+    pip install qs
+
+Usage
+-----
+
+Run it.
+";
+        let chunks = chunk(text, DocumentFormat::ReStructuredText);
+        let contexts: Vec<_> = chunks.iter().map(|c| c.context.as_deref()).collect();
+
+        assert_eq!(contexts, vec![Some("Guide > Setup"), Some("Guide > Usage")]);
+        assert!(chunks[0].text.contains("pip install qs"));
+    }
+
+    #[test]
+    fn test_asciidoc_and_org_headings() {
+        let adoc = "= Manual\n\n== Install\n\n----\n== not a heading\n----\n";
+        let chunks = chunk(adoc, DocumentFormat::AsciiDoc);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].context.as_deref(), Some("Manual > Install"));
+
+        let org = "* Notes\n** Model\nSome text.\n#+begin_src sh\n* not a heading\n#+end_src\n";
+        let chunks = chunk(org, DocumentFormat::Org);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].context.as_deref(), Some("Notes > Model"));
+    }
+}
@@ -0,0 +1,186 @@
+//! Text encoding detection and binary sniffing
+//!
+//! Files are decoded by byte order mark, then as BOM-less UTF-16, then as
+//! UTF-8, even with a few invalid bytes (a truncated character, a stray
+//! Latin-1 byte), then by an encoding declared near the top of the file
+//! (`<?xml encoding=...?>`, `<meta charset=...>`, `# -*- coding: ... -*-`).
+//! Anything else is read as Windows-1252, the superset of Latin-1 used by
+//! most legacy text.
+
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+/// How much of a file to look at when sniffing
+const SNIFF_LEN: usize = 8192;
+
+/// Check if content looks binary rather than text.
+///
+/// Content is binary if it has NUL bytes or more than 10% control characters
+/// in its first 8 KiB, unless it is UTF-16.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if Encoding::for_bom(head).is_some() || utf16_without_bom(head).is_some() {
+        return false;
+    }
+    if head.contains(&0) {
+        return true;
+    }
+
+    let control = head
+        .iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)) || b == 0x7f)
+        .count();
+    control * 10 > head.len()
+}
+
+/// Decode text in whatever encoding it was detected to be in.
+///
+/// A leading byte order mark is removed.
+pub fn decode(bytes: &[u8]) -> Cow<'_, str> {
+    detect(bytes).decode_with_bom_removal(bytes).0
+}
+
+/// Detect the encoding of some text.
+pub fn detect(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    // NUL-padded ASCII is valid UTF-8, so look for UTF-16 first
+    let head = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if let Some(encoding) = utf16_without_bom(head) {
+        return encoding;
+    }
+    if is_mostly_utf8(bytes) {
+        return UTF_8;
+    }
+
+    declared_encoding(head).unwrap_or(WINDOWS_1252)
+}
+
+/// Whether content is UTF-8 but for less than 1% of invalid bytes, which
+/// are decoded as replacement characters.
+///
+/// ASCII with a few high bytes is more likely a legacy encoding, so some
+/// valid non-ASCII character is needed as evidence of UTF-8.
+fn is_mostly_utf8(bytes: &[u8]) -> bool {
+    let (mut invalid, mut non_ascii) = (0, false);
+    for chunk in bytes.utf8_chunks() {
+        invalid += chunk.invalid().len();
+        non_ascii |= !chunk.valid().is_ascii();
+    }
+    invalid == 0 || (non_ascii && invalid * 100 < bytes.len())
+}
+
+/// Find an encoding declared in the head of a file, as an XML or Python
+/// `encoding`/`coding` declaration or an HTML `charset`.
+fn declared_encoding(head: &[u8]) -> Option<&'static Encoding> {
+    let head = &head[..head.len().min(1024)];
+    ["charset=", "encoding=", "coding:", "coding="]
+        .iter()
+        .find_map(|key| {
+            let start = find(head, key.as_bytes())? + key.len();
+            let label: Vec<u8> = head[start..]
+                .iter()
+                .skip_while(|b| matches!(b, b' ' | b'"' | b'\''))
+                .take_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+                .copied()
+                .collect();
+            Encoding::for_label(&label)
+        })
+        // A declared UTF-8 or UTF-16 is wrong if we got this far
+        .filter(|&e| e != UTF_8 && e != UTF_16LE && e != UTF_16BE)
+}
+
+/// Detect UTF-16 without a byte order mark, from the NUL bytes that make up
+/// the high half of ASCII characters.
+fn utf16_without_bom(head: &[u8]) -> Option<&'static Encoding> {
+    if head.len() < 4 {
+        return None;
+    }
+
+    let pairs = head.len() / 2;
+    let nul_at = |offset: usize| {
+        head.chunks_exact(2)
+            .filter(|pair| pair[offset] == 0 && pair[1 - offset] != 0)
+            .count()
+    };
+    if nul_at(1) * 10 >= pairs * 9 {
+        Some(UTF_16LE)
+    } else if nul_at(0) * 10 >= pairs * 9 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Position of the first occurrence of `needle`, ignoring ASCII case.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b"fn main() {}\n"));
+        assert!(!is_binary("café\n".as_bytes()));
+        assert!(!is_binary(&utf16le("hello world\n")));
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00\x00"));
+        assert!(is_binary(b"\x01\x02\x03\x04abc"));
+    }
+
+    #[test]
+    fn test_decode_bom() {
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(utf16le("def main():\n"));
+        assert_eq!(decode(&bytes), "def main():\n");
+
+        assert_eq!(decode(b"\xef\xbb\xbfhello"), "hello");
+    }
+
+    #[test]
+    fn test_decode_utf16_without_bom() {
+        assert_eq!(
+            decode(&utf16le("SELECT * FROM users;\n")),
+            "SELECT * FROM users;\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_legacy() {
+        // Latin-1 é is not valid UTF-8
+        assert_eq!(decode(b"caf\xe9\n"), "café\n");
+        let mut latin1 = b"caf\xe9 ".to_vec();
+        latin1.extend(b"x".repeat(200));
+        assert!(decode(&latin1).starts_with("café"));
+
+        // A declared encoding wins over the Windows-1252 default
+        let xml = b"<?xml version=\"1.0\" encoding=\"KOI8-R\"?>\n<p>\xf0\xd2\xc9\xd7\xc5\xd4</p>";
+        assert!(decode(xml).contains("Привет"));
+    }
+
+    #[test]
+    fn test_decode_mostly_utf8() {
+        // A character cut off at the end doesn't make the rest Latin-1
+        let mut bytes = "naïve ".repeat(40).into_bytes();
+        bytes.extend(&"é".as_bytes()[..1]);
+        let text = decode(&bytes);
+        assert!(text.starts_with("naïve naïve"));
+        assert!(text.ends_with('\u{fffd}'));
+
+        // Nor does it when an encoding is declared
+        let mut xml = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\n".to_vec();
+        xml.extend(&bytes);
+        assert!(decode(&xml).contains("naïve"));
+    }
+}
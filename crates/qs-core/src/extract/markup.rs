@@ -0,0 +1,508 @@
+//! Clean text extraction from HTML and XML
+//!
+//! Markup is converted to Markdown-like text: tags and attributes are
+//! dropped, `<script>` and `<style>` are skipped, HTML headings become
+//! Markdown headings and `<pre>` blocks become code fences. The converted
+//! text is chunked like a Markdown document, and every converted line
+//! remembers the source lines it came from, so chunks keep the line range
+//! of the original markup.
+
+use super::document::{self, DocumentFormat};
+use super::{Chunk, Measure};
+
+/// Supported markup formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkupFormat {
+    Html,
+    Xml,
+}
+
+impl MarkupFormat {
+    /// Detect markup format from file extension.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "html" | "htm" | "xhtml" => Some(Self::Html),
+            "xml" => Some(Self::Xml),
+            _ => None,
+        }
+    }
+}
+
+/// Split markup into chunks of clean text, with source line ranges.
+pub fn chunk_markup(
+    source: &str,
+    format: MarkupFormat,
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure,
+) -> Vec<Chunk> {
+    let (text, lines) = to_text(source, format);
+    let chunks = document::chunk_document(
+        &text,
+        DocumentFormat::Markdown,
+        chunk_size,
+        overlap,
+        measure,
+    );
+
+    // Map converted line numbers back to the source
+    let source_line = |line: usize| lines.get(line.saturating_sub(1)).copied();
+    chunks
+        .into_iter()
+        .filter(|chunk| !chunk.text.trim().is_empty())
+        .enumerate()
+        .map(|(index, chunk)| Chunk {
+            start_line: source_line(chunk.start_line).map_or(1, |l| l.0),
+            end_line: source_line(chunk.end_line).map_or(1, |l| l.1),
+            index,
+            ..chunk
+        })
+        .collect()
+}
+
+/// HTML elements that separate paragraphs.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "fieldset",
+    "figure",
+    "footer",
+    "form",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "section",
+    "table",
+    "title",
+    "ul",
+];
+
+/// HTML elements that start a new line within a paragraph.
+const LINE_ELEMENTS: &[&str] = &[
+    "br",
+    "caption",
+    "dd",
+    "dt",
+    "figcaption",
+    "li",
+    "option",
+    "summary",
+    "tr",
+];
+
+/// HTML elements whose content is not text.
+const RAW_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Builds the converted text line by line, tracking source lines.
+#[derive(Default)]
+struct Writer {
+    text: String,
+    /// First and last source line (1-indexed) of each converted line
+    lines: Vec<(usize, usize)>,
+    /// Whether the current converted line has content
+    open: bool,
+    /// Whether a space is due before the next word
+    space: bool,
+    /// Markdown prefix for the next line opened (a heading marker)
+    prefix: Option<String>,
+}
+
+impl Writer {
+    /// Append a word, opening a new line if needed.
+    fn word(&mut self, word: &str, line: usize) {
+        if self.open {
+            if self.space {
+                self.text.push(' ');
+            }
+            self.lines.last_mut().expect("open line").1 = line;
+        } else {
+            self.lines.push((line, line));
+            self.open = true;
+            match self.prefix.take() {
+                Some(prefix) => self.text.push_str(&prefix),
+                None if document::needs_escape(word) => self.text.push('\\'),
+                None => {}
+            }
+        }
+        self.text.push_str(word);
+        self.space = false;
+    }
+
+    /// Append text, collapsing whitespace. `line` is the source line it starts on.
+    fn text(&mut self, text: &str, mut line: usize) {
+        let mut word_start = None;
+        for (i, c) in text.char_indices() {
+            if c.is_whitespace() {
+                if let Some(start) = word_start.take() {
+                    self.word(&text[start..i], line);
+                }
+                self.space = true;
+                if c == '\n' {
+                    line += 1;
+                }
+            } else if word_start.is_none() {
+                word_start = Some(i);
+            }
+        }
+        if let Some(start) = word_start {
+            self.word(&text[start..], line);
+        }
+    }
+
+    /// Append preformatted text verbatim, line by line.
+    fn preformatted(&mut self, text: &str, mut line: usize) {
+        let mut parts = text.split('\n').peekable();
+        while let Some(part) = parts.next() {
+            if !part.is_empty() {
+                if !self.open {
+                    self.lines.push((line, line));
+                    self.open = true;
+                }
+                self.text.push_str(part);
+            }
+            if parts.peek().is_some() {
+                // Keep blank lines inside the block
+                if !self.open {
+                    self.lines.push((line, line));
+                }
+                self.text.push('\n');
+                self.open = false;
+                line += 1;
+            }
+        }
+    }
+
+    /// End the current line.
+    fn end_line(&mut self) {
+        if self.open {
+            self.text.push('\n');
+            self.open = false;
+        }
+        self.space = false;
+    }
+
+    /// End the current paragraph with a blank line.
+    fn end_block(&mut self) {
+        self.end_line();
+        let last = self.lines.last().map(|&(_, last)| last);
+        if let Some(last) = last.filter(|_| !self.text.ends_with("\n\n")) {
+            self.text.push('\n');
+            self.lines.push((last, last));
+        }
+        self.prefix = None;
+    }
+
+    /// Write a line of its own, such as a code fence.
+    fn marker(&mut self, marker: &str, line: usize) {
+        self.end_line();
+        self.lines.push((line, line));
+        self.text.push_str(marker);
+        self.text.push('\n');
+    }
+}
+
+/// Convert markup to clean text, returning the source line range of each line.
+fn to_text(source: &str, format: MarkupFormat) -> (String, Vec<(usize, usize)>) {
+    let html = format == MarkupFormat::Html;
+    let mut writer = Writer::default();
+    let mut pos = 0;
+    let mut line = 1;
+    let mut pre_depth = 0;
+
+    while pos < source.len() {
+        let rest = &source[pos..];
+
+        // Text up to the next tag
+        let Some(tag_start) = rest.find('<') else {
+            emit_text(&mut writer, rest, line, pre_depth > 0);
+            break;
+        };
+        if tag_start > 0 {
+            let text = &rest[..tag_start];
+            emit_text(&mut writer, text, line, pre_depth > 0);
+            line += text.matches('\n').count();
+            pos += tag_start;
+            continue;
+        }
+
+        let (consumed, tag) = read_tag(rest);
+        let raw = &rest[..consumed];
+        let tag_line = line;
+        line += raw.matches('\n').count();
+        pos += consumed;
+
+        let Some(tag) = tag else {
+            continue;
+        };
+        match tag {
+            Tag::Text(text) => {
+                emit_text(&mut writer, text, tag_line, pre_depth > 0);
+            }
+            Tag::Start(name) if html && RAW_ELEMENTS.contains(&name.as_str()) => {
+                // Skip everything up to the matching end tag
+                let content = &source[pos..];
+                let skipped = find_end_tag(content, &name).unwrap_or(content.len());
+                line += content[..skipped].matches('\n').count();
+                pos += skipped;
+            }
+            // Every XML element boundary ends a line
+            Tag::Start(_) | Tag::End(_) if !html => writer.end_line(),
+            Tag::Start(name) => {
+                if let Some(level) = heading_level(&name) {
+                    writer.end_block();
+                    writer.prefix = Some(format!("{} ", "#".repeat(level)));
+                } else if name == "pre" {
+                    writer.end_block();
+                    if pre_depth == 0 {
+                        writer.marker("```", tag_line);
+                    }
+                    pre_depth += 1;
+                } else if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                    writer.end_block();
+                } else if LINE_ELEMENTS.contains(&name.as_str()) {
+                    writer.end_line();
+                } else if matches!(name.as_str(), "td" | "th") {
+                    writer.space = true;
+                }
+            }
+            Tag::End(name) => {
+                if heading_level(&name).is_some() || BLOCK_ELEMENTS.contains(&name.as_str()) {
+                    writer.end_block();
+                } else if name == "pre" && pre_depth > 0 {
+                    pre_depth -= 1;
+                    if pre_depth == 0 {
+                        writer.marker("```", tag_line);
+                        writer.end_block();
+                    }
+                } else if LINE_ELEMENTS.contains(&name.as_str()) {
+                    writer.end_line();
+                }
+            }
+        }
+    }
+
+    writer.end_line();
+    (writer.text, writer.lines)
+}
+
+/// Append text content, decoding entities.
+fn emit_text(writer: &mut Writer, text: &str, line: usize, preformatted: bool) {
+    let text = decode_entities(text);
+    if preformatted {
+        writer.preformatted(&text, line);
+    } else {
+        writer.text(&text, line);
+    }
+}
+
+/// A piece of markup starting with `<`.
+#[derive(Debug, PartialEq, Eq)]
+enum Tag<'a> {
+    /// Start tag, with its lowercase name
+    Start(String),
+    /// End tag, with its lowercase name
+    End(String),
+    /// Literal text, from CDATA or a `<` that doesn't start a tag
+    Text(&'a str),
+}
+
+/// Read the markup at the start of `input`, which begins with `<`.
+///
+/// Returns the number of bytes consumed and the tag, or `None` for
+/// comments, declarations and processing instructions.
+fn read_tag(input: &str) -> (usize, Option<Tag<'_>>) {
+    let skip_to = |end: &str| input.find(end).map_or(input.len(), |p| p + end.len());
+
+    if input.starts_with("<!--") {
+        return (skip_to("-->"), None);
+    }
+    if let Some(cdata) = input.strip_prefix("<![CDATA[") {
+        let end = cdata.find("]]>").unwrap_or(cdata.len());
+        let consumed = (9 + end + 3).min(input.len());
+        return (consumed, Some(Tag::Text(&cdata[..end])));
+    }
+    if input.starts_with("<!") || input.starts_with("<?") {
+        return (skip_to(">"), None);
+    }
+
+    let (closing, name_start) = match input.strip_prefix("</") {
+        Some(_) => (true, 2),
+        None => (false, 1),
+    };
+    let name_len = input[name_start..]
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(input.len() - name_start);
+    let name = &input[name_start..name_start + name_len];
+
+    // A `<` that doesn't start a tag is text
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return (1, Some(Tag::Text("<")));
+    }
+
+    // Find the closing `>`, skipping over quoted attribute values
+    let mut quote = None;
+    let mut end = input.len();
+    let attrs_start = name_start + name_len;
+    for (i, c) in input[attrs_start..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => {
+                end = attrs_start + i + 1;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let name = name.to_ascii_lowercase();
+    // Self-closing tags like <br/> only matter for their start
+    let tag = if closing {
+        Tag::End(name)
+    } else {
+        Tag::Start(name)
+    };
+    (end, Some(tag))
+}
+
+/// Find the end tag of an element, ignoring case.
+fn find_end_tag(text: &str, name: &str) -> Option<usize> {
+    text.match_indices("</").map(|(i, _)| i).find(|&i| {
+        text[i + 2..]
+            .get(..name.len())
+            .is_some_and(|n| n.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Heading level of an `h1`-`h6` element name.
+fn heading_level(name: &str) -> Option<usize> {
+    let level = name.strip_prefix('h')?.parse().ok()?;
+    (1..=6).contains(&level).then_some(level)
+}
+
+/// Decode the common named and all numeric character references.
+fn decode_entities(text: &str) -> std::borrow::Cow<'_, str> {
+    if !text.contains('&') {
+        return text.into();
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..1 + end]);
+        let resolved = entity.and_then(|name| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let number = name.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+
+        match (entity, resolved) {
+            (Some(name), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[name.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::count_chars;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = r#"<!DOCTYPE html>
+<html>
+<head>
+  <style>body { color: red; }</style>
+  <script>var x = "<h1>nope</h1>";</script>
+</head>
+<body>
+  <h1 class="title">Guide</h1>
+  <p>Use <a href="https://example.com">the
+  client</a> &amp; retry.</p>
+  <!-- <p>hidden</p> -->
+  <h2>Install</h2>
+  <pre>cargo install qs
+  qs init</pre>
+</body>
+</html>
+"#;
+        let (text, _) = to_text(html, MarkupFormat::Html);
+        assert_eq!(
+            text,
+            "# Guide\n\nUse the client & retry.\n\n## Install\n\n```\ncargo install qs\n  qs init\n```\n\n"
+        );
+    }
+
+    #[test]
+    fn test_html_chunks_map_to_source_lines() {
+        let html = "<h1>Guide</h1>\n<p>Intro\ntext</p>\n<h2>Install</h2>\n<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n";
+        let chunks = chunk_markup(html, MarkupFormat::Html, 1000, 0, &count_chars);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].text, "# Guide\n\nIntro text\n");
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 3));
+        assert_eq!(chunks[1].context.as_deref(), Some("Guide > Install"));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (4, 7));
+    }
+
+    #[test]
+    fn test_xml_to_text() {
+        let xml = r#"<?xml version="1.0"?>
+<config>
+  <name>billing</name>
+  <note><![CDATA[a < b]]></note>
+  <retry max="3">exponential</retry>
+</config>
+"#;
+        let (text, lines) = to_text(xml, MarkupFormat::Xml);
+        assert_eq!(text, "billing\na < b\nexponential\n");
+        assert_eq!(lines, [(3, 3), (4, 4), (5, 5)]);
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("a &lt;b&gt; &#39;c&#x27; &copy; & d"),
+            "a <b> 'c' &copy; & d"
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Jupyter notebook extraction, one cell at a time
+//!
+//! The notebook JSON is converted to the text of its cells, separated by
+//! [`PAGE_BREAK`]. Each cell starts with a header line naming its kind (and
+//! language, for code cells), so the chunker can parse code cells with
+//! tree-sitter and split markdown cells along their headings.
+
+use std::path::Path;
+
+use serde_json::Value;
+
+use super::document::{self, DocumentFormat};
+use super::{Chunk, Measure, PAGE_BREAK, chunk_text_by, split_large_chunks};
+use crate::Result;
+use crate::parse::{CodeLanguage, CodeParser};
+
+/// Header of the section holding the outputs of the preceding code cell
+const OUTPUT_HEADER: &str = "output";
+
+/// Check if a path has an `.ipynb` extension.
+pub fn is_notebook(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("ipynb"))
+}
+
+/// Extract the cells of a notebook from its JSON source, separated by
+/// [`PAGE_BREAK`].
+///
+/// Cell outputs are only included if `outputs` is set.
+pub fn text_from_json(content: &str, outputs: bool) -> Result<String> {
+    let notebook: Value = serde_json::from_str(content)?;
+
+    // Code cells are in the kernel's language, Python unless stated otherwise
+    let metadata = &notebook["metadata"];
+    let language = metadata["language_info"]["name"]
+        .as_str()
+        .or_else(|| metadata["kernelspec"]["language"].as_str())
+        .unwrap_or("python");
+
+    let mut sections = Vec::new();
+    for cell in notebook["cells"].as_array().into_iter().flatten() {
+        let kind = cell["cell_type"].as_str().unwrap_or("raw");
+        let header = match kind {
+            "code" => format!("code {}", language),
+            other => other.to_string(),
+        };
+        sections.push(section(&header, &join_source(&cell["source"])));
+
+        if outputs && kind == "code" {
+            let text: String = cell["outputs"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(output_text)
+                .collect();
+            if !text.trim().is_empty() {
+                sections.push(section(OUTPUT_HEADER, &text));
+            }
+        }
+    }
+
+    Ok(sections.join(&PAGE_BREAK.to_string()))
+}
+
+/// A section of the extracted text: a header line, then the body.
+fn section(header: &str, body: &str) -> String {
+    format!("{}\n{}", header, body.replace(PAGE_BREAK, ""))
+}
+
+/// Join a multiline string, stored either as a string or as a list of lines.
+fn join_source(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// The plain text of a cell output: streams, `text/plain` results and errors.
+fn output_text(output: &Value) -> String {
+    let mut text = match output["output_type"].as_str() {
+        Some("stream") => join_source(&output["text"]),
+        Some("execute_result" | "display_data") => join_source(&output["data"]["text/plain"]),
+        Some("error") => format!(
+            "{}: {}",
+            output["ename"].as_str().unwrap_or_default(),
+            output["evalue"].as_str().unwrap_or_default()
+        ),
+        _ => String::new(),
+    };
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// Chunk extracted notebook text cell by cell.
+///
+/// Code cells are parsed with tree-sitter when the language is compiled in,
+/// markdown cells are split along their headings, and outputs and raw cells
+/// are chunked as text. Chunks never span cells, and line numbers are relative
+/// to the cell. Code chunks without a name of their own take the heading of
+/// the markdown above them as context.
+pub fn chunk_notebook(
+    text: &str,
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure,
+    parser: &mut CodeParser,
+) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut cell = 0;
+    let mut heading: Option<String> = None;
+
+    for section in text.split(PAGE_BREAK) {
+        let (header, body) = section.split_once('\n').unwrap_or((section, ""));
+        let mut words = header.split_whitespace();
+        let kind = words.next().unwrap_or_default();
+        if kind != OUTPUT_HEADER {
+            cell += 1;
+        }
+        if body.trim().is_empty() {
+            continue;
+        }
+
+        let sub = match kind {
+            "code" => {
+                let parsed = words
+                    .next()
+                    .and_then(CodeLanguage::from_name)
+                    .and_then(|lang| parser.parse_source(lang, body))
+                    .filter(|c| !c.is_empty());
+                let sub = match parsed {
+                    Some(sub) => split_large_chunks(sub, chunk_size, overlap, measure),
+                    None => chunk_text_by(body, chunk_size, overlap, measure),
+                };
+                sub.into_iter()
+                    .map(|chunk| Chunk {
+                        context: chunk.context.or_else(|| heading.clone()),
+                        ..chunk
+                    })
+                    .collect()
+            }
+            "markdown" => {
+                let sub = document::chunk_document(
+                    body,
+                    DocumentFormat::Markdown,
+                    chunk_size,
+                    overlap,
+                    measure,
+                );
+                if let Some(context) = sub.iter().rev().find_map(|c| c.context.clone()) {
+                    heading = Some(context);
+                }
+                sub
+            }
+            _ => chunk_text_by(body, chunk_size, overlap, measure),
+        };
+
+        for chunk in sub {
+            chunks.push(Chunk {
+                index: chunks.len(),
+                cell: Some(cell),
+                ..chunk
+            });
+        }
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+  "metadata": {"kernelspec": {"name": "python3", "language": "python"}},
+  "nbformat": 4,
+  "cells": [
+    {"cell_type": "markdown", "metadata": {}, "source": ["# Load data\n", "Read the raw CSV."]},
+    {
+      "cell_type": "code",
+      "metadata": {},
+      "execution_count": 1,
+      "source": "import pandas as pd\ndf = pd.read_csv(\"sales.csv\")",
+      "outputs": [{"output_type": "stream", "name": "stdout", "text": ["loaded 1200 rows\n"]}]
+    },
+    {"cell_type": "code", "metadata": {}, "source": [], "outputs": []},
+    {
+      "cell_type": "code",
+      "metadata": {},
+      "source": ["def clean(df):\n", "    return df.dropna()\n"],
+      "outputs": [{"output_type": "error", "ename": "KeyError", "evalue": "'total'", "traceback": []}]
+    }
+  ]
+}"##;
+
+    #[test]
+    fn test_extract_cells() {
+        let text = text_from_json(NOTEBOOK, false).unwrap();
+        let sections: Vec<&str> = text.split(PAGE_BREAK).collect();
+
+        assert_eq!(sections.len(), 4);
+        assert_eq!(sections[0], "markdown\n# Load data\nRead the raw CSV.");
+        assert!(sections[1].starts_with("code python\nimport pandas"));
+        assert!(!text.contains("loaded 1200 rows"));
+    }
+
+    #[test]
+    fn test_extract_outputs() {
+        let text = text_from_json(NOTEBOOK, true).unwrap();
+        let sections: Vec<&str> = text.split(PAGE_BREAK).collect();
+
+        assert_eq!(sections.len(), 6);
+        assert_eq!(sections[2], "output\nloaded 1200 rows\n");
+        assert_eq!(sections[5], "output\nKeyError: 'total'\n");
+    }
+
+    #[test]
+    fn test_malformed_notebook() {
+        assert!(text_from_json("{\"cells\": [", false).is_err());
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_chunk_notebook() {
+        let text = text_from_json(NOTEBOOK, true).unwrap();
+        let mut parser = CodeParser::new();
+        let chunks = chunk_notebook(&text, 2000, 200, &crate::extract::count_chars, &mut parser);
+
+        let cells: Vec<_> = chunks.iter().map(|c| c.cell).collect();
+        assert_eq!(cells, [Some(1), Some(2), Some(2), Some(4), Some(4)]);
+
+        // Code without definitions keeps the markdown heading as context
+        assert!(chunks[1].text.starts_with("import pandas"));
+        assert_eq!(chunks[1].context.as_deref(), Some("Load data"));
+
+        // Definitions are parsed, with lines relative to the cell
+        assert!(chunks[3].text.starts_with("def clean(df):"));
+        assert_eq!((chunks[3].start_line, chunks[3].end_line), (1, 2));
+        assert_eq!(chunks[4].text, "KeyError: 'total'\n");
+        assert_eq!(chunks[4].index, 4);
+    }
+}
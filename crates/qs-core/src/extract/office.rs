@@ -0,0 +1,847 @@
+//! Text extraction from OOXML and ODF office documents
+//!
+//! Documents are unzipped and their XML parsed locally. Each format is
+//! converted to an intermediate text that the chunker can split back along
+//! the document's structure:
+//!
+//! - DOCX and ODT become Markdown, one paragraph per line, so they are
+//!   chunked by heading and paragraph.
+//! - XLSX becomes one section per sheet: the sheet name, then one line per
+//!   row, prefixed with the row number and with cells separated by tabs.
+//! - PPTX becomes one section per slide: the slide title, then its text.
+//!
+//! Sections are separated by [`PAGE_BREAK`]. Every chunk records where it
+//! sits in the document (e.g. `paragraphs 3-7`, `Budget, rows 2-40` or
+//! `slide 4`) as its location.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek};
+use std::path::Path;
+
+use quick_xml::Reader;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use zip::ZipArchive;
+
+use super::document::{self, DocumentFormat};
+use super::{Chunk, Measure, PAGE_BREAK, chunk_text_by};
+use crate::{QsError, Result};
+
+/// Supported office document formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfficeFormat {
+    Docx,
+    Xlsx,
+    Pptx,
+    Odt,
+}
+
+impl OfficeFormat {
+    /// Detect office format from file extension.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "docx" => Some(Self::Docx),
+            "xlsx" => Some(Self::Xlsx),
+            "pptx" => Some(Self::Pptx),
+            "odt" => Some(Self::Odt),
+            _ => None,
+        }
+    }
+
+    /// Detect office format from a file path.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+}
+
+/// Columns of a worksheet, up to XFD
+const MAX_COLUMNS: usize = 16_384;
+
+/// Spaces a single `<text:s>` element may expand to
+const MAX_SPACES: usize = 256;
+
+/// Extract the text of an office document in its intermediate form.
+///
+/// Parts larger than `max_part_size` once decompressed are an error.
+pub fn extract_text(content: &[u8], format: OfficeFormat, max_part_size: u64) -> Result<String> {
+    text_from_reader(Cursor::new(content), format, max_part_size).map_err(QsError::Extract)
+}
+
+type ExtractResult<T> = std::result::Result<T, String>;
+
+/// A zipped document, whose parts are read up to a size limit.
+struct Package<R> {
+    archive: ZipArchive<R>,
+    max_part_size: u64,
+}
+
+impl<R: Read + Seek> Package<R> {
+    /// Read a part of the document as UTF-8 text.
+    ///
+    /// Sizes in zip headers can lie, so reading stops past the limit.
+    fn read(&mut self, name: &str) -> ExtractResult<String> {
+        let entry = self
+            .archive
+            .by_name(name)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        let mut xml = String::new();
+        entry
+            .take(self.max_part_size + 1)
+            .read_to_string(&mut xml)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        if xml.len() as u64 > self.max_part_size {
+            return Err(format!(
+                "{}: larger than {} bytes once decompressed",
+                name, self.max_part_size
+            ));
+        }
+        Ok(xml)
+    }
+}
+
+fn text_from_reader(
+    reader: impl Read + Seek,
+    format: OfficeFormat,
+    max_part_size: u64,
+) -> ExtractResult<String> {
+    let archive = ZipArchive::new(reader).map_err(|e| e.to_string())?;
+    let mut package = Package {
+        archive,
+        max_part_size,
+    };
+
+    match format {
+        OfficeFormat::Docx => {
+            let xml = package.read("word/document.xml")?;
+            Ok(to_markdown(&docx_paragraphs(&xml)?))
+        }
+        OfficeFormat::Odt => {
+            let xml = package.read("content.xml")?;
+            Ok(to_markdown(&odt_paragraphs(&xml)?))
+        }
+        OfficeFormat::Xlsx => xlsx_text(&mut package),
+        OfficeFormat::Pptx => pptx_text(&mut package),
+    }
+}
+
+/// Chunk the intermediate text of an office document.
+pub fn chunk_office(
+    text: &str,
+    format: OfficeFormat,
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure,
+) -> Vec<Chunk> {
+    match format {
+        OfficeFormat::Docx | OfficeFormat::Odt => {
+            let chunks = document::chunk_document(
+                text,
+                DocumentFormat::Markdown,
+                chunk_size,
+                overlap,
+                measure,
+            );
+            // Paragraphs sit on odd lines, separated by blank lines
+            let paragraph = |line: usize| line.div_ceil(2);
+            chunks
+                .into_iter()
+                .map(|chunk| Chunk {
+                    location: Some(range_location(
+                        "paragraph",
+                        paragraph(chunk.start_line),
+                        paragraph(chunk.end_line),
+                    )),
+                    ..chunk
+                })
+                .collect()
+        }
+        OfficeFormat::Xlsx => chunk_sheets(text, chunk_size, overlap, measure),
+        OfficeFormat::Pptx => chunk_slides(text, chunk_size, overlap, measure),
+    }
+}
+
+/// Format a location like `rows 2-40`, or `row 2` for a single item.
+fn range_location(item: &str, first: usize, last: usize) -> String {
+    if first == last {
+        format!("{} {}", item, first)
+    } else {
+        format!("{}s {}-{}", item, first, last)
+    }
+}
+
+/// Split text into `PAGE_BREAK`-separated sections of a title line and a body.
+fn sections(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    text.split(PAGE_BREAK)
+        .map(|section| section.split_once('\n').unwrap_or((section, "")))
+}
+
+/// Chunk sheets into groups of consecutive rows.
+fn chunk_sheets(text: &str, chunk_size: usize, overlap: usize, measure: Measure) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+
+    for (name, body) in sections(text) {
+        // Rows are `<number>\t<cells>`; group them without splitting any row
+        let rows: Vec<(usize, &str)> = body
+            .lines()
+            .filter_map(|line| {
+                let (number, cells) = line.split_once('\t')?;
+                Some((number.parse().ok()?, cells))
+            })
+            .collect();
+
+        let push_rows = |rows: &[(usize, &str)], chunks: &mut Vec<Chunk>| {
+            let (Some(&(first, _)), Some(&(last, _))) = (rows.first(), rows.last()) else {
+                return;
+            };
+            let text: Vec<&str> = rows.iter().map(|(_, cells)| *cells).collect();
+            chunks.push(Chunk {
+                text: text.join("\n"),
+                start_line: first,
+                end_line: last,
+                index: chunks.len(),
+                context: Some(name.to_string()),
+                location: Some(format!("{}, {}", name, range_location("row", first, last))),
+                ..Default::default()
+            });
+        };
+
+        // Rows are packed into chunks whole, like document blocks
+        let mut current = 0..0;
+        for (i, &(number, cells)) in rows.iter().enumerate() {
+            if !current.is_empty() {
+                let candidate: Vec<&str> = rows[current.start..=i].iter().map(|r| r.1).collect();
+                if measure(&candidate.join("\n")) <= chunk_size {
+                    current.end = i + 1;
+                    continue;
+                }
+                push_rows(&rows[current.clone()], &mut chunks);
+            }
+
+            if measure(cells) <= chunk_size {
+                current = i..i + 1;
+                continue;
+            }
+
+            // A row larger than a chunk on its own has to be split
+            current = i + 1..i + 1;
+            for part in chunk_text_by(cells, chunk_size, overlap, measure) {
+                chunks.push(Chunk {
+                    start_line: number,
+                    end_line: number,
+                    index: chunks.len(),
+                    context: Some(name.to_string()),
+                    location: Some(format!("{}, row {}", name, number)),
+                    ..part
+                });
+            }
+        }
+        push_rows(&rows[current], &mut chunks);
+    }
+
+    chunks
+}
+
+/// Chunk slides one at a time, titled by the slide title.
+fn chunk_slides(text: &str, chunk_size: usize, overlap: usize, measure: Measure) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+
+    for (number, (title, body)) in sections(text).enumerate() {
+        if body.trim().is_empty() {
+            continue;
+        }
+        let context = Some(title.to_string()).filter(|t| !t.is_empty());
+        for part in chunk_text_by(body, chunk_size, overlap, measure) {
+            chunks.push(Chunk {
+                index: chunks.len(),
+                context: context.clone(),
+                location: Some(format!("slide {}", number + 1)),
+                ..part
+            });
+        }
+    }
+
+    chunks
+}
+
+/// An XML event, with empty elements reported as a start and an end.
+enum Xml<'a> {
+    Start(&'a BytesStart<'a>),
+    /// Local name of the closed element
+    End(&'a [u8]),
+    Text(&'a str),
+}
+
+/// Visit the events of an XML document, with references resolved to text.
+fn walk_xml(xml: &str, mut visit: impl FnMut(Xml)) -> ExtractResult<()> {
+    let mut reader = Reader::from_str(xml);
+
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(e) => visit(Xml::Start(&e)),
+            Event::Empty(e) => {
+                visit(Xml::Start(&e));
+                visit(Xml::End(e.local_name().as_ref()));
+            }
+            Event::End(e) => visit(Xml::End(e.local_name().as_ref())),
+            Event::Text(e) => visit(Xml::Text(&e.decode().map_err(|e| e.to_string())?)),
+            Event::CData(e) => visit(Xml::Text(&e.decode().map_err(|e| e.to_string())?)),
+            Event::GeneralRef(e) => {
+                let text = match e.resolve_char_ref() {
+                    Ok(Some(c)) => Cow::Owned(c.to_string()),
+                    _ => {
+                        let name = e.decode().map_err(|e| e.to_string())?;
+                        Cow::Borrowed(resolve_predefined_entity(&name).unwrap_or_default())
+                    }
+                };
+                visit(Xml::Text(&text));
+            }
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Read an attribute by local name (ignoring its namespace prefix).
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok())
+        .map(Cow::into_owned)
+}
+
+/// Read the relationship ID (`r:id`) of an element, which may also have a plain `id`.
+fn relationship_id(element: &BytesStart) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.prefix().is_some() && a.key.local_name().as_ref() == b"id")
+        .and_then(|a| a.unescape_value().ok())
+        .map(Cow::into_owned)
+}
+
+/// A paragraph of a word processing document.
+#[derive(Debug, Default)]
+struct Paragraph {
+    /// Outline level, for headings
+    heading: Option<usize>,
+    text: String,
+}
+
+/// Collects paragraphs, merging nested ones (e.g. text boxes) into their parent.
+#[derive(Default)]
+struct Paragraphs {
+    done: Vec<Paragraph>,
+    current: Paragraph,
+    depth: usize,
+}
+
+impl Paragraphs {
+    fn start(&mut self, heading: Option<usize>) {
+        if self.depth == 0 {
+            self.current = Paragraph {
+                heading,
+                ..Default::default()
+            };
+        }
+        self.depth += 1;
+    }
+
+    fn end(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            let paragraph = std::mem::take(&mut self.current);
+            if !paragraph.text.trim().is_empty() {
+                self.done.push(paragraph);
+            }
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        if self.depth > 0 {
+            self.current.text.push_str(text);
+        }
+    }
+}
+
+/// Parse the paragraphs of a DOCX `word/document.xml`.
+fn docx_paragraphs(xml: &str) -> ExtractResult<Vec<Paragraph>> {
+    let mut paragraphs = Paragraphs::default();
+    let mut in_run = false;
+    let mut in_text = false;
+
+    walk_xml(xml, |event| match event {
+        Xml::Start(e) => match e.local_name().as_ref() {
+            b"p" => paragraphs.start(None),
+            b"pStyle" => {
+                // Built-in heading styles are named "Heading1".."Heading9" and "Title"
+                let style = attribute(e, b"val").unwrap_or_default().to_lowercase();
+                let level = match style.as_str() {
+                    "title" => Some(1),
+                    _ => style
+                        .strip_prefix("heading")
+                        .and_then(|n| n.parse::<usize>().ok()),
+                };
+                if level.is_some() {
+                    paragraphs.current.heading = level;
+                }
+            }
+            b"outlineLvl" => {
+                let level = attribute(e, b"val").and_then(|v| v.parse::<usize>().ok());
+                // Level 9 marks body text
+                if let Some(level @ 0..=8) = level {
+                    paragraphs.current.heading = Some(level + 1);
+                }
+            }
+            b"r" => in_run = true,
+            b"t" => in_text = true,
+            b"tab" if in_run => paragraphs.push("\t"),
+            b"br" | b"cr" if in_run => paragraphs.push(" "),
+            _ => {}
+        },
+        Xml::End(name) => match name {
+            b"p" => paragraphs.end(),
+            b"r" => in_run = false,
+            b"t" => in_text = false,
+            _ => {}
+        },
+        Xml::Text(text) if in_text => paragraphs.push(text),
+        Xml::Text(_) => {}
+    })?;
+
+    Ok(paragraphs.done)
+}
+
+/// Parse the paragraphs of an ODT `content.xml`.
+fn odt_paragraphs(xml: &str) -> ExtractResult<Vec<Paragraph>> {
+    let mut paragraphs = Paragraphs::default();
+    // Depth inside elements whose text isn't part of the body (comments)
+    let mut skip = 0;
+
+    walk_xml(xml, |event| match event {
+        Xml::Start(_) if skip > 0 => skip += 1,
+        Xml::End(_) if skip > 0 => skip -= 1,
+        Xml::Text(_) if skip > 0 => {}
+        Xml::Start(e) => match e.local_name().as_ref() {
+            b"h" => {
+                let level = attribute(e, b"outline-level").and_then(|v| v.parse().ok());
+                paragraphs.start(Some(level.unwrap_or(1)));
+            }
+            b"p" => paragraphs.start(None),
+            b"s" => {
+                let count = attribute(e, b"c").and_then(|c| c.parse().ok()).unwrap_or(1);
+                paragraphs.push(&" ".repeat(usize::min(count, MAX_SPACES)));
+            }
+            b"tab" => paragraphs.push("\t"),
+            b"line-break" => paragraphs.push(" "),
+            b"annotation" | b"note" => skip = 1,
+            _ => {}
+        },
+        Xml::End(name) => {
+            if matches!(name, b"h" | b"p") {
+                paragraphs.end();
+            }
+        }
+        Xml::Text(text) => paragraphs.push(text),
+    })?;
+
+    Ok(paragraphs.done)
+}
+
+/// Render paragraphs as Markdown, one paragraph per line.
+fn to_markdown(paragraphs: &[Paragraph]) -> String {
+    let mut markdown = String::new();
+
+    for paragraph in paragraphs {
+        let text = paragraph
+            .text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        match paragraph.heading {
+            Some(level) => {
+                markdown.push_str(&"#".repeat(level.clamp(1, 6)));
+                markdown.push(' ');
+            }
+            // Keep body text from being read as Markdown structure
+            None if document::needs_escape(&text) => markdown.push('\\'),
+            None => {}
+        }
+        markdown.push_str(&text);
+        markdown.push_str("\n\n");
+    }
+
+    markdown
+}
+
+/// Read the relationships of a part, mapping IDs to archive paths.
+fn relationships<R: Read + Seek>(
+    package: &mut Package<R>,
+    dir: &str,
+    rels: &str,
+) -> ExtractResult<HashMap<String, String>> {
+    let xml = package.read(rels)?;
+    let mut targets = HashMap::new();
+
+    walk_xml(&xml, |event| {
+        if let Xml::Start(e) = event {
+            let id = attribute(e, b"Id");
+            let target = attribute(e, b"Target");
+            if let (Some(id), Some(target)) = (id, target) {
+                // Targets are relative to the part's directory, or absolute
+                let path = match target.strip_prefix('/') {
+                    Some(absolute) => absolute.to_string(),
+                    None => format!("{}/{}", dir, target),
+                };
+                targets.insert(id, path);
+            }
+        }
+    })?;
+
+    Ok(targets)
+}
+
+/// Convert an XLSX workbook to sheet sections.
+fn xlsx_text<R: Read + Seek>(package: &mut Package<R>) -> ExtractResult<String> {
+    let targets = relationships(package, "xl", "xl/_rels/workbook.xml.rels")?;
+
+    let mut sheets = Vec::new();
+    walk_xml(&package.read("xl/workbook.xml")?, |event| match event {
+        Xml::Start(e) if e.local_name().as_ref() == b"sheet" => {
+            let name = attribute(e, b"name").unwrap_or_default();
+            let path = relationship_id(e).and_then(|id| targets.get(&id).cloned());
+            sheets.push((name, path));
+        }
+        _ => {}
+    })?;
+
+    // Strings are stored once, and referenced by index from cells
+    let mut shared = Vec::new();
+    if let Ok(xml) = package.read("xl/sharedStrings.xml") {
+        let mut current = String::new();
+        let mut in_text = false;
+        // Phonetic guides (<rPh>) repeat the text in another script
+        let mut in_phonetic = false;
+        walk_xml(&xml, |event| match event {
+            Xml::Start(e) => match e.local_name().as_ref() {
+                b"t" => in_text = !in_phonetic,
+                b"rPh" => in_phonetic = true,
+                _ => {}
+            },
+            Xml::End(b"t") => in_text = false,
+            Xml::End(b"rPh") => in_phonetic = false,
+            Xml::End(b"si") => shared.push(std::mem::take(&mut current)),
+            Xml::End(_) => {}
+            Xml::Text(text) if in_text => current.push_str(text),
+            Xml::Text(_) => {}
+        })?;
+    }
+
+    let mut sections = Vec::new();
+    for (name, path) in sheets {
+        let Some(path) = path else { continue };
+        let xml = package.read(&path)?;
+        sections.push(format!("{}\n{}", name, sheet_rows(&xml, &shared)?));
+    }
+
+    Ok(sections.join(&PAGE_BREAK.to_string()))
+}
+
+/// Render the non-empty rows of a worksheet as `<number>\t<cells>` lines.
+fn sheet_rows(xml: &str, shared: &[String]) -> ExtractResult<String> {
+    let mut rows = String::new();
+    let mut row_number = 0;
+    let mut cells: Vec<String> = Vec::new();
+    let mut cell_type = String::new();
+    let mut column = Some(0);
+    let mut value = String::new();
+    let mut in_value = false;
+
+    walk_xml(xml, |event| match event {
+        Xml::Start(e) => match e.local_name().as_ref() {
+            b"row" => {
+                row_number = attribute(e, b"r")
+                    .and_then(|r| r.parse().ok())
+                    .unwrap_or(row_number + 1);
+                cells.clear();
+            }
+            b"c" => {
+                cell_type = attribute(e, b"t").unwrap_or_default();
+                column = match attribute(e, b"r") {
+                    Some(r) => column_index(&r),
+                    None => Some(cells.len()).filter(|&c| c < MAX_COLUMNS),
+                };
+                value.clear();
+            }
+            b"v" | b"t" => in_value = true,
+            _ => {}
+        },
+        Xml::End(name) => match name {
+            b"v" | b"t" => in_value = false,
+            b"c" => {
+                // Cells past the last column are invalid, and dropped
+                let Some(column) = column else { return };
+                let text = match cell_type.as_str() {
+                    "s" => value
+                        .trim()
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|i| shared.get(i))
+                        .cloned()
+                        .unwrap_or_default(),
+                    "b" => (if value.trim() == "1" { "TRUE" } else { "FALSE" }).to_string(),
+                    _ => std::mem::take(&mut value),
+                };
+                // Pad skipped columns so cells stay aligned with their headers
+                if cells.len() < column {
+                    cells.resize(column, String::new());
+                }
+                cells.push(text.replace(['\t', '\n'], " "));
+            }
+            b"row" if cells.iter().any(|c| !c.trim().is_empty()) => {
+                rows.push_str(&format!("{}\t{}\n", row_number, cells.join("\t")));
+            }
+            _ => {}
+        },
+        Xml::Text(text) if in_value => value.push_str(text),
+        Xml::Text(_) => {}
+    })?;
+
+    Ok(rows)
+}
+
+/// Zero-based column index of a cell reference like `B7` or `AA12`, or
+/// `None` past the last column (XFD).
+fn column_index(reference: &str) -> Option<usize> {
+    reference
+        .chars()
+        .take_while(char::is_ascii_alphabetic)
+        .try_fold(0usize, |index, c| {
+            let index = index * 26 + (c.to_ascii_uppercase() as usize - 'A' as usize + 1);
+            (index <= MAX_COLUMNS).then_some(index)
+        })
+        .map(|index| index.saturating_sub(1))
+}
+
+/// Convert a PPTX presentation to slide sections, in presentation order.
+fn pptx_text<R: Read + Seek>(package: &mut Package<R>) -> ExtractResult<String> {
+    let targets = relationships(package, "ppt", "ppt/_rels/presentation.xml.rels")?;
+
+    let mut slides = Vec::new();
+    walk_xml(
+        &package.read("ppt/presentation.xml")?,
+        |event| match event {
+            Xml::Start(e) if e.local_name().as_ref() == b"sldId" => {
+                slides.extend(relationship_id(e).and_then(|id| targets.get(&id)).cloned());
+            }
+            _ => {}
+        },
+    )?;
+
+    let mut sections = Vec::new();
+    for path in slides {
+        sections.push(slide_text(&package.read(&path)?)?);
+    }
+
+    Ok(sections.join(&PAGE_BREAK.to_string()))
+}
+
+/// Render a slide as its title line followed by one line per paragraph.
+fn slide_text(xml: &str) -> ExtractResult<String> {
+    let mut title = String::new();
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut in_text = false;
+    // Whether the current shape is the title placeholder, and its text
+    let mut title_shape = false;
+    let mut shape_text = String::new();
+
+    walk_xml(xml, |event| match event {
+        Xml::Start(e) => match e.local_name().as_ref() {
+            b"sp" => {
+                title_shape = false;
+                shape_text.clear();
+            }
+            b"ph" => {
+                let kind = attribute(e, b"type").unwrap_or_default();
+                title_shape = kind == "title" || kind == "ctrTitle";
+            }
+            b"t" => in_text = true,
+            b"br" => line.push(' '),
+            _ => {}
+        },
+        Xml::End(name) => match name {
+            b"t" => in_text = false,
+            b"p" => {
+                let text = line.split_whitespace().collect::<Vec<_>>().join(" ");
+                line.clear();
+                if !text.is_empty() {
+                    if title_shape {
+                        if !shape_text.is_empty() {
+                            shape_text.push(' ');
+                        }
+                        shape_text.push_str(&text);
+                    }
+                    lines.push(text);
+                }
+            }
+            b"sp" => {
+                if title_shape && title.is_empty() {
+                    title = std::mem::take(&mut shape_text);
+                }
+                title_shape = false;
+            }
+            _ => {}
+        },
+        Xml::Text(text) if in_text => line.push_str(text),
+        Xml::Text(_) => {}
+    })?;
+
+    Ok(format!("{}\n{}", title, lines.join("\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::count_chars;
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    /// Build an uncompressed zip archive from (path, content) pairs.
+    fn make_zip(files: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (path, content) in files {
+            writer.start_file(*path, options).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let mut cursor = writer.finish().unwrap();
+        cursor.set_position(0);
+        cursor
+    }
+
+    #[test]
+    fn test_docx() {
+        let document = r#"<w:document xmlns:w="w"><w:body>
+<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Setup</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">Install </w:t></w:r><w:r><w:t>R&amp;D tools.</w:t></w:r></w:p>
+<w:p/>
+<w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>Linux</w:t></w:r></w:p>
+<w:p><w:r><w:t>#1 use apt</w:t></w:r></w:p>
+</w:body></w:document>"#;
+        let zip = make_zip(&[("word/document.xml", document)]);
+        let text = text_from_reader(zip, OfficeFormat::Docx, 1 << 20).unwrap();
+
+        assert_eq!(
+            text,
+            "# Setup\n\nInstall R&D tools.\n\n## Linux\n\n\\#1 use apt\n\n"
+        );
+
+        let chunks = chunk_office(&text, OfficeFormat::Docx, 1000, 0, &count_chars);
+        let last = chunks.last().unwrap();
+        assert_eq!(last.context.as_deref(), Some("Setup > Linux"));
+        assert_eq!(last.location.as_deref(), Some("paragraphs 3-4"));
+
+        // Parts are read up to the size limit, whatever their header says
+        let zip = make_zip(&[("word/document.xml", document)]);
+        let error = text_from_reader(zip, OfficeFormat::Docx, 64).unwrap_err();
+        assert!(error.contains("larger than 64 bytes"), "{}", error);
+    }
+
+    #[test]
+    fn test_odt() {
+        let content = r#"<office:document-content xmlns:office="o" xmlns:text="t"><office:body><office:text>
+<text:h text:outline-level="1">Intro</text:h>
+<text:p>Hello<text:s text:c="2"/>world<office:annotation><text:p>a comment</text:p></office:annotation></text:p>
+</office:text></office:body></office:document-content>"#;
+        let zip = make_zip(&[("content.xml", content)]);
+        let text = text_from_reader(zip, OfficeFormat::Odt, 1 << 20).unwrap();
+
+        assert_eq!(text, "# Intro\n\nHello world\n\n");
+    }
+
+    #[test]
+    fn test_xlsx() {
+        let zip = make_zip(&[
+            (
+                "xl/workbook.xml",
+                r#"<workbook xmlns:r="r"><sheets><sheet name="Budget" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships><Relationship Id="rId1" Target="worksheets/sheet1.xml"/></Relationships>"#,
+            ),
+            (
+                "xl/sharedStrings.xml",
+                r#"<sst><si><t>Item</t></si><si><t>Cost</t></si><si><r><t>Rent</t></r></si></sst>"#,
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                r#"<worksheet><sheetData>
+<row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v>1</v></c></row>
+<row r="4"><c r="A4" t="s"><v>2</v></c><c r="C4"><v>1200</v></c></row>
+</sheetData></worksheet>"#,
+            ),
+        ]);
+        let text = text_from_reader(zip, OfficeFormat::Xlsx, 1 << 20).unwrap();
+        assert_eq!(text, "Budget\n1\tItem\tCost\n4\tRent\t\t1200\n");
+
+        let chunks = chunk_office(&text, OfficeFormat::Xlsx, 1000, 0, &count_chars);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].text, "Item\tCost\nRent\t\t1200");
+        assert_eq!(chunks[0].location.as_deref(), Some("Budget, rows 1-4"));
+
+        // Small chunks split between rows
+        let chunks = chunk_office(&text, OfficeFormat::Xlsx, 12, 0, &count_chars);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].location.as_deref(), Some("Budget, row 4"));
+    }
+
+    #[test]
+    fn test_pptx() {
+        let slide = |title: &str, body: &str| {
+            format!(
+                r#"<p:sld xmlns:p="p" xmlns:a="a"><p:cSld><p:spTree>
+<p:sp><p:nvSpPr><p:nvPr><p:ph type="title"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>{}</a:t></a:r></a:p></p:txBody></p:sp>
+<p:sp><p:txBody><a:p><a:r><a:t>{}</a:t></a:r></a:p></p:txBody></p:sp>
+</p:spTree></p:cSld></p:sld>"#,
+                title, body
+            )
+        };
+        let (first, second) = (slide("Roadmap", "Ship v2"), slide("Risks", "Hiring"));
+        let zip = make_zip(&[
+            (
+                "ppt/presentation.xml",
+                r#"<p:presentation xmlns:p="p" xmlns:r="r"><p:sldIdLst><p:sldId id="256" r:id="rId3"/><p:sldId id="257" r:id="rId2"/></p:sldIdLst></p:presentation>"#,
+            ),
+            (
+                "ppt/_rels/presentation.xml.rels",
+                r#"<Relationships><Relationship Id="rId2" Target="slides/slide1.xml"/><Relationship Id="rId3" Target="/ppt/slides/slide2.xml"/></Relationships>"#,
+            ),
+            ("ppt/slides/slide1.xml", &first),
+            ("ppt/slides/slide2.xml", &second),
+        ]);
+        let text = text_from_reader(zip, OfficeFormat::Pptx, 1 << 20).unwrap();
+
+        let chunks = chunk_office(&text, OfficeFormat::Pptx, 1000, 0, &count_chars);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].text, "Risks\nHiring");
+        assert_eq!(chunks[0].context.as_deref(), Some("Risks"));
+        assert_eq!(chunks[0].location.as_deref(), Some("slide 1"));
+        assert_eq!(chunks[1].location.as_deref(), Some("slide 2"));
+    }
+
+    #[test]
+    fn test_column_index() {
+        assert_eq!(column_index("A1"), Some(0));
+        assert_eq!(column_index("C4"), Some(2));
+        assert_eq!(column_index("AA10"), Some(26));
+        assert_eq!(column_index("XFD1"), Some(MAX_COLUMNS - 1));
+        assert_eq!(column_index("XFE1"), None);
+        assert_eq!(column_index(&"Z".repeat(40)), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! PDF text extraction, one page at a time
+//!
+//! Pages are joined with form feeds (as `pdftotext` does), so the extracted
+//! text flows through the indexer like any other file and is split back into
+//! pages when chunking.
+
+use std::path::Path;
+
+use super::{Chunk, Measure, PAGE_BREAK, chunk_text_by};
+use crate::{QsError, Result};
+
+/// Check if a path has a `.pdf` extension.
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("pdf"))
+}
+
+/// Extract the text of a PDF, with pages separated by [`PAGE_BREAK`].
+pub fn extract_text(content: &[u8]) -> Result<String> {
+    text_from_bytes(content).map_err(QsError::Extract)
+}
+
+fn text_from_bytes(bytes: &[u8]) -> std::result::Result<String, String> {
+    // pdf-extract panics on some malformed files; treat that as a failed extraction
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes))
+        .map_err(|_| "malformed PDF".to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let pages: Vec<String> = pages
+        .iter()
+        .map(|page| page.replace(PAGE_BREAK, ""))
+        .collect();
+    Ok(pages.join(&PAGE_BREAK.to_string()))
+}
+
+/// Chunk extracted PDF text page by page.
+///
+/// Chunks never span pages. Line numbers are relative to the page.
+pub fn chunk_pages(text: &str, chunk_size: usize, overlap: usize, measure: Measure) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+
+    for (number, page) in text.split(PAGE_BREAK).enumerate() {
+        if page.trim().is_empty() {
+            continue;
+        }
+        for chunk in chunk_text_by(page, chunk_size, overlap, measure) {
+            chunks.push(Chunk {
+                index: chunks.len(),
+                page: Some(number + 1),
+                ..chunk
+            });
+        }
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::count_chars;
+
+    /// Build a PDF with one page per string, each drawn in Helvetica.
+    fn make_pdf(pages: &[&str]) -> Vec<u8> {
+        let font_id = 3 + pages.len() * 2;
+        let kids: Vec<String> = (0..pages.len())
+            .map(|i| format!("{} 0 R", 3 + i * 2))
+            .collect();
+
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                pages.len()
+            ),
+        ];
+        for (i, text) in pages.iter().enumerate() {
+            let content = format!("BT /F1 12 Tf 72 720 Td ({}) Tj ET", text);
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+                 /Resources << /Font << /F1 {} 0 R >> >> /Contents {} 0 R >>",
+                font_id,
+                4 + i * 2
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                content.len(),
+                content
+            ));
+        }
+        objects.push(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_string(),
+        );
+
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+        }
+        let xref = pdf.len();
+        pdf.push_str(&format!(
+            "xref\n0 {}\n0000000000 65535 f \n",
+            objects.len() + 1
+        ));
+        for offset in offsets {
+            pdf.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        pdf.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        ));
+        pdf.into_bytes()
+    }
+
+    #[test]
+    fn test_extract_pages() {
+        let pdf = make_pdf(&["Introduction", "Retry policy"]);
+        let text = text_from_bytes(&pdf).unwrap();
+
+        let pages: Vec<&str> = text.split(PAGE_BREAK).map(str::trim).collect();
+        assert_eq!(pages, ["Introduction", "Retry policy"]);
+    }
+
+    #[test]
+    fn test_chunk_pages() {
+        let text = "first page\n\u{c}\u{c}third page\nmore\n";
+        let chunks = chunk_pages(text, 100, 0, &count_chars);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].page, Some(1));
+        assert_eq!(chunks[1].page, Some(3));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (1, 2));
+        assert_eq!(chunks[1].index, 1);
+    }
+
+    #[test]
+    fn test_malformed_pdf() {
+        assert!(text_from_bytes(b"%PDF-1.4\nnot really").is_err());
+    }
+}
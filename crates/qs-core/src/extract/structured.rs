@@ -0,0 +1,872 @@
+//! Structural chunking for JSON, YAML and TOML files
+//!
+//! Splits configuration and data files by top-level key (or YAML document)
+//! instead of by character count. Every chunk carries its key path, e.g.
+//! `dependencies.tokio`, and oversized objects and arrays are split
+//! recursively along their children.
+
+use std::ops::Range;
+
+use super::{Chunk, Measure, chunk_text_by};
+
+/// Maximum nesting depth followed when parsing JSON.
+const MAX_DEPTH: usize = 128;
+
+/// Supported structured data formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructuredFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl StructuredFormat {
+    /// Detect structured format from file extension.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+}
+
+/// A keyed entry in the source, with the byte range it spans.
+#[derive(Debug, Clone)]
+struct Node {
+    /// Key path from the root, e.g. `dependencies.tokio` or `items[3]`
+    path: String,
+    /// Byte range of the entry, including its key
+    range: Range<usize>,
+    /// Nested entries, if the value is an object or array
+    children: Vec<Node>,
+    /// Whether the children are array elements rather than keys
+    is_array: bool,
+}
+
+/// Split a structured data file into chunks by key.
+///
+/// Returns `None` if the text cannot be parsed, so callers can fall back to
+/// plain text chunking.
+pub fn chunk_structured(
+    text: &str,
+    format: StructuredFormat,
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure,
+) -> Option<Vec<Chunk>> {
+    let root = match format {
+        StructuredFormat::Json => scan_json(text)?,
+        StructuredFormat::Yaml => scan_yaml(text),
+        StructuredFormat::Toml => scan_toml(text),
+    };
+    if root.children.is_empty() {
+        return None;
+    }
+
+    let mut chunker = Chunker {
+        text,
+        line_starts: line_starts(text),
+        chunk_size,
+        overlap,
+        measure,
+        chunks: Vec::new(),
+    };
+
+    if root.is_array {
+        // Root arrays have no keys to split by, so pack their elements
+        chunker.pack(&root);
+    } else {
+        for node in &root.children {
+            chunker.node(node);
+        }
+    }
+
+    Some(chunker.chunks)
+}
+
+/// Turns a node tree into size-limited chunks.
+struct Chunker<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+    chunk_size: usize,
+    overlap: usize,
+    measure: Measure<'a>,
+    chunks: Vec<Chunk>,
+}
+
+impl Chunker<'_> {
+    /// Emit a node as one chunk, or split it along its children if too large.
+    fn node(&mut self, node: &Node) {
+        if (self.measure)(&self.text[node.range.clone()]) <= self.chunk_size {
+            self.push(node.range.clone(), &node.path);
+        } else if node.children.is_empty() {
+            self.split(node.range.clone(), &node.path);
+        } else {
+            self.pack(node);
+        }
+    }
+
+    /// Pack consecutive children of a node into chunks.
+    ///
+    /// A group of several children is labelled with the parent's path; a
+    /// child on its own keeps its full path.
+    fn pack(&mut self, parent: &Node) {
+        let mut group: Option<(usize, usize)> = None;
+
+        for (i, child) in parent.children.iter().enumerate() {
+            if let Some((first, last)) = group {
+                let start = parent.children[first].range.start;
+                if (self.measure)(&self.text[start..child.range.end]) <= self.chunk_size {
+                    group = Some((first, i));
+                    continue;
+                }
+                self.push_group(parent, first, last);
+            }
+            group = Some((i, i));
+        }
+
+        if let Some((first, last)) = group {
+            self.push_group(parent, first, last);
+        }
+    }
+
+    fn push_group(&mut self, parent: &Node, first: usize, last: usize) {
+        if first == last {
+            self.node(&parent.children[first]);
+        } else {
+            let range = parent.children[first].range.start..parent.children[last].range.end;
+            self.push(range, &parent.path);
+        }
+    }
+
+    /// Split an oversized leaf value as plain text.
+    fn split(&mut self, range: Range<usize>, path: &str) {
+        let start_line = self.line_of(range.start);
+        for mut sub in chunk_text_by(
+            &self.text[range],
+            self.chunk_size,
+            self.overlap,
+            self.measure,
+        ) {
+            sub.start_line += start_line - 1;
+            sub.end_line += start_line - 1;
+            sub.index = self.chunks.len();
+            sub.context = context(path);
+            self.chunks.push(sub);
+        }
+    }
+
+    fn push(&mut self, range: Range<usize>, path: &str) {
+        let last = range.end.saturating_sub(1).max(range.start);
+        self.chunks.push(Chunk {
+            text: self.text[range.clone()].to_string(),
+            start_line: self.line_of(range.start),
+            end_line: self.line_of(last),
+            index: self.chunks.len(),
+            context: context(path),
+            ..Default::default()
+        });
+    }
+
+    /// Line number (1-indexed) containing a byte offset.
+    fn line_of(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+}
+
+fn context(path: &str) -> Option<String> {
+    (!path.is_empty()).then(|| path.to_string())
+}
+
+/// Byte offsets at which each line starts.
+fn line_starts(text: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+/// Append a key to a path.
+fn join_key(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Shrink a range so it neither starts nor ends with whitespace.
+fn trim_range(text: &str, range: Range<usize>) -> Range<usize> {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+    start..end.max(start)
+}
+
+/// Parse JSON into a node tree, keeping byte spans.
+fn scan_json(text: &str) -> Option<Node> {
+    let mut parser = JsonParser {
+        text,
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    parser.skip_whitespace();
+    let start = parser.pos;
+    let (children, is_array) = parser.value("", 0)?;
+    let end = parser.pos;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return None;
+    }
+
+    Some(Node {
+        path: String::new(),
+        range: start..end,
+        children,
+        is_array,
+    })
+}
+
+/// Minimal span-preserving JSON parser.
+struct JsonParser<'a> {
+    text: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parse a value, returning its children and whether it is an array.
+    fn value(&mut self, path: &str, depth: usize) -> Option<(Vec<Node>, bool)> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        self.skip_whitespace();
+        match self.bytes.get(self.pos)? {
+            b'{' => self.object(path, depth).map(|c| (c, false)),
+            b'[' => self.array(path, depth).map(|c| (c, true)),
+            b'"' => self.string().map(|_| (Vec::new(), false)),
+            _ => {
+                // Numbers, booleans and null
+                let start = self.pos;
+                while self.pos < self.bytes.len()
+                    && !matches!(self.bytes[self.pos], b',' | b'}' | b']')
+                    && !self.bytes[self.pos].is_ascii_whitespace()
+                {
+                    self.pos += 1;
+                }
+                (self.pos > start).then(|| (Vec::new(), false))
+            }
+        }
+    }
+
+    fn object(&mut self, path: &str, depth: usize) -> Option<Vec<Node>> {
+        self.pos += 1;
+        let mut children = Vec::new();
+        if self.eat(b'}') {
+            return Some(children);
+        }
+
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let key = self.string()?;
+            if !self.eat(b':') {
+                return None;
+            }
+            let child_path = join_key(path, &key);
+            let (grandchildren, is_array) = self.value(&child_path, depth + 1)?;
+            children.push(Node {
+                path: child_path,
+                range: start..self.pos,
+                children: grandchildren,
+                is_array,
+            });
+
+            if self.eat(b',') {
+                continue;
+            }
+            return self.eat(b'}').then_some(children);
+        }
+    }
+
+    fn array(&mut self, path: &str, depth: usize) -> Option<Vec<Node>> {
+        self.pos += 1;
+        let mut children = Vec::new();
+        if self.eat(b']') {
+            return Some(children);
+        }
+
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let child_path = format!("{}[{}]", path, children.len());
+            let (grandchildren, is_array) = self.value(&child_path, depth + 1)?;
+            children.push(Node {
+                path: child_path,
+                range: start..self.pos,
+                children: grandchildren,
+                is_array,
+            });
+
+            if self.eat(b',') {
+                continue;
+            }
+            return self.eat(b']').then_some(children);
+        }
+    }
+
+    /// Parse a string literal and return its decoded value.
+    fn string(&mut self) -> Option<String> {
+        if self.bytes.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        let start = self.pos;
+        self.pos += 1;
+        while self.pos < self.bytes.len() {
+            match self.bytes[self.pos] {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+                    return serde_json::from_str(&self.text[start..self.pos]).ok();
+                }
+                _ => self.pos += 1,
+            }
+        }
+        None
+    }
+}
+
+/// A source line with its byte span.
+#[derive(Clone, Copy)]
+struct Line<'a> {
+    text: &'a str,
+    start: usize,
+    end: usize,
+}
+
+impl Line<'_> {
+    fn indent(&self) -> usize {
+        self.text.len() - self.text.trim_start().len()
+    }
+
+    /// Blank lines and comments don't start entries.
+    fn is_trivia(&self) -> bool {
+        let trimmed = self.text.trim();
+        trimmed.is_empty() || trimmed.starts_with('#')
+    }
+}
+
+fn split_lines(text: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for segment in text.split_inclusive('\n') {
+        let end = start + segment.len();
+        lines.push(Line {
+            text: segment.trim_end_matches(['\n', '\r']),
+            start,
+            end,
+        });
+        start = end;
+    }
+    lines
+}
+
+/// Scan YAML into documents and keys using indentation.
+///
+/// Multiple documents separated by `---` become top-level entries of their
+/// own; a single document is split by its top-level keys.
+fn scan_yaml(text: &str) -> Node {
+    let lines = split_lines(text);
+
+    // Split into documents at `---` markers
+    let mut documents = Vec::new();
+    let mut doc_start = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let marker = line.text.trim_end();
+        if marker == "---" || marker.starts_with("--- ") || marker == "..." {
+            if lines[doc_start..i].iter().any(|l| !l.is_trivia()) {
+                documents.push(doc_start..i);
+            }
+            doc_start = i + 1;
+        }
+    }
+    if lines[doc_start..].iter().any(|l| !l.is_trivia()) {
+        documents.push(doc_start..lines.len());
+    }
+
+    if documents.len() == 1 {
+        let doc = documents.remove(0);
+        let (children, is_array) = yaml_entries(text, &lines, doc, 0, "");
+        return Node {
+            path: String::new(),
+            range: 0..text.len(),
+            children,
+            is_array,
+        };
+    }
+
+    let children = documents
+        .into_iter()
+        .enumerate()
+        .map(|(n, doc)| {
+            let range = trim_range(text, lines[doc.start].start..lines[doc.end - 1].end);
+            let path = format!("document[{}]", n);
+            let (children, is_array) = yaml_entries(text, &lines, doc, 0, &path);
+            Node {
+                path,
+                range,
+                children,
+                is_array,
+            }
+        })
+        .collect();
+
+    Node {
+        path: String::new(),
+        range: 0..text.len(),
+        children,
+        is_array: false,
+    }
+}
+
+/// Find the entries at `indent` within a range of lines.
+///
+/// Returns the entries and whether they are sequence items.
+fn yaml_entries(
+    text: &str,
+    lines: &[Line],
+    range: Range<usize>,
+    indent: usize,
+    path: &str,
+) -> (Vec<Node>, bool) {
+    let starts: Vec<usize> = range
+        .clone()
+        .filter(|&i| !lines[i].is_trivia() && lines[i].indent() == indent)
+        .collect();
+    let Some(&first) = starts.first() else {
+        return (Vec::new(), false);
+    };
+
+    let is_item = |i: usize| {
+        let t = lines[i].text.trim_start();
+        t == "-" || t.starts_with("- ")
+    };
+    let is_array = is_item(first);
+
+    // In a mapping, sequence items at the key's own indent belong to the key
+    let starts: Vec<usize> = starts
+        .into_iter()
+        .filter(|&i| is_array || !is_item(i))
+        .collect();
+
+    let mut nodes = Vec::new();
+    for (n, &start) in starts.iter().enumerate() {
+        let end = starts.get(n + 1).copied().unwrap_or(range.end);
+        // Comments before the first entry belong to it
+        let first_line = if n == 0 { range.start } else { start };
+        let byte_range = trim_range(text, lines[first_line].start..lines[end - 1].end);
+
+        let (child_path, children, child_is_array) = if is_array {
+            let child_path = format!("{}[{}]", path, n);
+            let (children, child_is_array) =
+                yaml_item_entries(text, lines, start..end, indent, &child_path);
+            (child_path, children, child_is_array)
+        } else {
+            let line = lines[start].text.trim_start();
+            let (key, value) = line.split_once(':').unwrap_or((line, ""));
+            let key = key.trim().trim_matches(['"', '\'']);
+            let child_path = join_key(path, key);
+
+            // Nested mapping or sequence on the following lines, unless the
+            // key has an inline or block scalar value
+            let value = value.trim();
+            let is_nested = value.is_empty() || value.starts_with(['#', '&']);
+            let body = start + 1..end;
+            let child_indent = body
+                .clone()
+                .find(|&i| !lines[i].is_trivia())
+                .map(|i| lines[i].indent())
+                .filter(|_| is_nested);
+            let (children, child_is_array) = match child_indent {
+                Some(child_indent) if child_indent >= indent => {
+                    yaml_entries(text, lines, body, child_indent, &child_path)
+                }
+                _ => (Vec::new(), false),
+            };
+            (child_path, children, child_is_array)
+        };
+
+        nodes.push(Node {
+            path: child_path,
+            range: byte_range,
+            children,
+            is_array: child_is_array,
+        });
+    }
+
+    (nodes, is_array)
+}
+
+/// Find the entries of a sequence item whose dash is at `indent`.
+///
+/// The item is a mapping or sequence when its first entry follows the dash,
+/// as in `- name: web`, or a nested block when the dash stands alone.
+fn yaml_item_entries(
+    text: &str,
+    lines: &[Line],
+    item: Range<usize>,
+    indent: usize,
+    path: &str,
+) -> (Vec<Node>, bool) {
+    let line = lines[item.start];
+    let content = line.text.trim_start()[1..].trim_start();
+
+    if content.is_empty() || content.starts_with('#') {
+        let body = item.start + 1..item.end;
+        return match body.clone().find(|&i| !lines[i].is_trivia()) {
+            Some(i) if lines[i].indent() > indent => {
+                yaml_entries(text, lines, body, lines[i].indent(), path)
+            }
+            _ => (Vec::new(), false),
+        };
+    }
+
+    let is_sequence = content == "-" || content.starts_with("- ");
+    let is_mapping =
+        !content.starts_with(['[', '{']) && (content.ends_with(':') || content.contains(": "));
+    if !is_sequence && !is_mapping {
+        return (Vec::new(), false);
+    }
+
+    // Scan the item as if its first entry were indented like the rest,
+    // replacing the dash with a space
+    let entry_indent = line.text.len() - content.len();
+    let padded = format!("{:width$}{}", "", content, width = entry_indent);
+    let mut item_lines = lines[item].to_vec();
+    item_lines[0].text = &padded;
+    yaml_entries(text, &item_lines, 0..item_lines.len(), entry_indent, path)
+}
+
+/// Scan TOML into tables and keys.
+///
+/// Root keys and `[table]` / `[[array]]` sections are top-level entries; the
+/// keys inside a table are its children.
+fn scan_toml(text: &str) -> Node {
+    let lines = split_lines(text);
+    let starts = toml_statement_starts(&lines);
+
+    // Group statements into the root table and `[table]` sections
+    let mut sections: Vec<(Option<String>, Vec<usize>)> = vec![(None, Vec::new())];
+    for &i in &starts {
+        let line = lines[i].text.trim();
+        if line.starts_with('[') {
+            sections.push((Some(toml_header(line)), vec![i]));
+        } else if let Some((_, statements)) = sections.last_mut() {
+            statements.push(i);
+        }
+    }
+
+    let mut array_counts: Vec<(String, usize)> = Vec::new();
+    let mut children = Vec::new();
+
+    for (n, (header, statements)) in sections.iter().enumerate() {
+        let Some(&first) = statements.first() else {
+            continue;
+        };
+        let end = sections
+            .get(n + 1)
+            .and_then(|(_, s)| s.first().copied())
+            .unwrap_or(lines.len());
+
+        let keys = |path: &str, key_lines: &[usize]| -> Vec<Node> {
+            key_lines
+                .iter()
+                .enumerate()
+                .map(|(k, &i)| {
+                    let key_end = key_lines.get(k + 1).copied().unwrap_or(end);
+                    Node {
+                        path: join_key(path, &toml_key(lines[i].text)),
+                        range: trim_range(text, lines[i].start..lines[key_end - 1].end),
+                        children: Vec::new(),
+                        is_array: false,
+                    }
+                })
+                .collect()
+        };
+
+        match header {
+            // Root keys are top-level entries on their own
+            None => children.extend(keys("", statements)),
+            Some(name) => {
+                let is_array_table = lines[first].text.trim().starts_with("[[");
+                let path = if is_array_table {
+                    let count = match array_counts.iter_mut().find(|(n, _)| n == name) {
+                        Some((_, count)) => {
+                            *count += 1;
+                            *count - 1
+                        }
+                        None => {
+                            array_counts.push((name.clone(), 1));
+                            0
+                        }
+                    };
+                    format!("{}[{}]", name, count)
+                } else {
+                    name.clone()
+                };
+
+                children.push(Node {
+                    range: trim_range(text, lines[first].start..lines[end - 1].end),
+                    children: keys(&path, &statements[1..]),
+                    path,
+                    is_array: false,
+                });
+            }
+        }
+    }
+
+    Node {
+        path: String::new(),
+        range: 0..text.len(),
+        children,
+        is_array: false,
+    }
+}
+
+/// Indices of lines that start a TOML statement (key or table header).
+///
+/// Lines inside multi-line strings and arrays are continuations.
+fn toml_statement_starts(lines: &[Line]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut depth = 0usize;
+    let mut multiline: Option<&str> = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        if depth == 0 && multiline.is_none() && !line.is_trivia() {
+            starts.push(i);
+        }
+
+        // Track brackets and strings that continue onto the next line
+        let text = line.text;
+        let mut pos = 0;
+        let is_header = depth == 0 && multiline.is_none() && text.trim_start().starts_with('[');
+        while pos < text.len() {
+            let rest = &text[pos..];
+            if let Some(delimiter) = multiline {
+                match rest.find(delimiter) {
+                    Some(p) => {
+                        pos += p + 3;
+                        multiline = None;
+                    }
+                    None => break,
+                }
+                continue;
+            }
+            if rest.starts_with("\"\"\"") || rest.starts_with("'''") {
+                multiline = Some(&rest[..3]);
+                pos += 3;
+                continue;
+            }
+            match rest.as_bytes()[0] {
+                b'#' => break,
+                b'"' | b'\'' => {
+                    let quote = rest.as_bytes()[0];
+                    let mut j = 1;
+                    while j < rest.len() && rest.as_bytes()[j] != quote {
+                        j += if quote == b'"' && rest.as_bytes()[j] == b'\\' {
+                            2
+                        } else {
+                            1
+                        };
+                    }
+                    pos += (j + 1).min(rest.len());
+                    continue;
+                }
+                b'[' | b'{' if !is_header => depth += 1,
+                b']' | b'}' if !is_header => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            pos += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    starts
+}
+
+/// Extract the table name from a `[table]` or `[[array]]` header line.
+fn toml_header(line: &str) -> String {
+    line.trim_start_matches('[')
+        .split(']')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// Extract the key from a `key = value` line.
+fn toml_key(line: &str) -> String {
+    line.split_once('=')
+        .map_or(line, |(key, _)| key)
+        .trim()
+        .trim_matches(['"', '\''])
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::count_chars;
+
+    fn contexts(chunks: &[Chunk]) -> Vec<&str> {
+        chunks
+            .iter()
+            .map(|c| c.context.as_deref().unwrap_or(""))
+            .collect()
+    }
+
+    #[test]
+    fn test_toml_tables() {
+        let text = r#"[package]
+name = "qs"
+version = "0.1.0"
+
+[dependencies]
+tokio = { version = "1.0", features = [
+    "full",
+] }
+serde = "1.0"
+"#;
+        let chunks = chunk_structured(text, StructuredFormat::Toml, 2000, 0, &count_chars).unwrap();
+        assert_eq!(contexts(&chunks), vec!["package", "dependencies"]);
+        assert_eq!(chunks[1].start_line, 5);
+        assert_eq!(chunks[1].end_line, 9);
+
+        // Oversized tables are split by key
+        let chunks = chunk_structured(text, StructuredFormat::Toml, 60, 0, &count_chars).unwrap();
+        assert_eq!(
+            contexts(&chunks),
+            vec!["package", "dependencies.tokio", "dependencies.serde"]
+        );
+        assert!(chunks[1].text.ends_with("] }"));
+    }
+
+    #[test]
+    fn test_toml_array_tables() {
+        let text = r#"[[bin]]
+name = "qs"
+path = "src/main.rs"
+
+[[bin]]
+name = "qs-server"
+path = "src/server.rs"
+"#;
+        // Keys of each table are labelled with its index
+        let chunks = chunk_structured(text, StructuredFormat::Toml, 25, 0, &count_chars).unwrap();
+        assert_eq!(
+            contexts(&chunks),
+            vec!["bin[0].name", "bin[0].path", "bin[1].name", "bin[1].path"]
+        );
+    }
+
+    #[test]
+    fn test_json_keys() {
+        let text = r#"{
+  "name": "app",
+  "scripts": {
+    "build": "tsc",
+    "test": "jest --coverage --runInBand"
+  },
+  "items": [1, 2, 3]
+}"#;
+        let chunks = chunk_structured(text, StructuredFormat::Json, 2000, 0, &count_chars).unwrap();
+        assert_eq!(contexts(&chunks), vec!["name", "scripts", "items"]);
+        assert_eq!(chunks[1].start_line, 3);
+        assert_eq!(chunks[1].end_line, 6);
+
+        let chunks = chunk_structured(text, StructuredFormat::Json, 40, 0, &count_chars).unwrap();
+        assert_eq!(
+            contexts(&chunks),
+            vec!["name", "scripts.build", "scripts.test", "items"]
+        );
+
+        assert!(
+            chunk_structured("{ invalid", StructuredFormat::Json, 40, 0, &count_chars).is_none()
+        );
+    }
+
+    #[test]
+    fn test_yaml_documents_and_keys() {
+        let text = "\
+apiVersion: v1
+kind: Service
+---
+apiVersion: apps/v1
+kind: Deployment
+spec:
+  replicas: 3
+  template:
+    containers:
+    - name: web
+      image: nginx
+";
+        let chunks = chunk_structured(text, StructuredFormat::Yaml, 2000, 0, &count_chars).unwrap();
+        assert_eq!(contexts(&chunks), vec!["document[0]", "document[1]"]);
+        assert_eq!(chunks[1].start_line, 4);
+
+        let chunks = chunk_structured(text, StructuredFormat::Yaml, 40, 0, &count_chars).unwrap();
+        assert_eq!(
+            contexts(&chunks),
+            vec![
+                "document[0]",
+                "document[1]",
+                "document[1].spec.replicas",
+                "document[1].spec.template.containers[0]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_yaml_sequence_items() {
+        let text = "\
+steps:
+- name: build
+  run: cargo build --release --all-features
+- uses: actions/checkout@v4
+-
+  name: test
+  run: cargo test --workspace --all-targets
+";
+        // Oversized items are split by their keys
+        let chunks = chunk_structured(text, StructuredFormat::Yaml, 45, 0, &count_chars).unwrap();
+        assert_eq!(
+            contexts(&chunks),
+            vec![
+                "steps[0].name",
+                "steps[0].run",
+                "steps[1]",
+                "steps[2].name",
+                "steps[2].run"
+            ]
+        );
+        assert_eq!(chunks[0].text, "- name: build");
+        assert_eq!(chunks[3].start_line, 6);
+    }
+}
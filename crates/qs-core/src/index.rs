@@ -7,7 +7,12 @@ use ignore::WalkBuilder;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    Config, Result, Storage, discover, embed::Embedder, extract, parse::CodeParser,
+    Config, DEFAULT_VECTOR, QsError, Result, Storage,
+    config::ChunkUnit,
+    discover,
+    embed::{self, EmbeddingBackend, VectorSpace},
+    extract::{self, ExtractContext, Extractor, Extractors},
+    parse::CodeParser,
     storage::ChunkPayload,
 };
 
@@ -76,10 +81,12 @@ impl FileIndex {
 pub struct Indexer {
     root: PathBuf,
     config: Config,
-    embedder: Embedder,
+    /// Embedders of each vector, the default one first
+    spaces: Vec<VectorSpace>,
     storage: Storage,
     file_index: FileIndex,
     parser: CodeParser,
+    extractors: Extractors,
     progress_callback: Option<ProgressCallback>,
 }
 
@@ -97,18 +104,55 @@ impl Indexer {
     /// Create a new indexer for a qs repository.
     pub fn new(root: PathBuf) -> Result<Self> {
         let config = Config::load(&root)?;
-        let embedder = Embedder::new(&config)?;
-        let storage = Storage::open(&root, &config)?;
+        let spaces = embed::vector_spaces(&config)?;
+        Self::build(root, config, spaces)
+    }
+
+    /// Create an indexer that embeds chunks with the given backend instead
+    /// of the configured model, filling the default vector only.
+    pub fn with_backend(root: PathBuf, embedder: Box<dyn EmbeddingBackend>) -> Result<Self> {
+        let config = Config::load(&root)?;
+        let spaces = vec![VectorSpace {
+            name: DEFAULT_VECTOR.to_string(),
+            embedder,
+        }];
+        Self::build(root, config, spaces)
+    }
+
+    fn build(root: PathBuf, config: Config, spaces: Vec<VectorSpace>) -> Result<Self> {
+        // Chunks are measured with the default model's tokenizer
+        let embedder = &spaces[0].embedder;
+        if config.chunk_unit == ChunkUnit::Tokens && !embedder.has_tokenizer() {
+            return Err(QsError::Config(format!(
+                "chunk_unit \"tokens\" needs a model with a tokenizer, which {} does not have; use \"chars\"",
+                embedder.model_id()
+            )));
+        }
+        let storage = Storage::open(&root, &embed::shard_models(&spaces), &config.storage)?;
         let file_index = FileIndex::load(&root)?;
-        let parser = CodeParser::new();
+        let mut parser = CodeParser::new();
+        for grammar in &config.grammars {
+            let query_path = grammar.query_path(&root);
+            let query = std::fs::read_to_string(&query_path)
+                .map_err(|e| QsError::Grammar(format!("{}: {}", query_path.display(), e)))?;
+            parser.load_grammar(
+                &grammar.name,
+                &grammar.library_path(&root),
+                &query,
+                &grammar.extensions,
+            )?;
+        }
+        parser.load_queries(&discover::queries_path(&root))?;
+        let extractors = Extractors::new(&config);
 
         Ok(Self {
             root,
             config,
-            embedder,
+            spaces,
             storage,
             file_index,
             parser,
+            extractors,
             progress_callback: None,
         })
     }
@@ -118,6 +162,12 @@ impl Indexer {
         self.progress_callback = Some(callback);
     }
 
+    /// Register an extractor for files the built-in ones do not handle, or
+    /// handle differently. Registered extractors are tried first, in order.
+    pub fn add_extractor(&mut self, extractor: Box<dyn Extractor>) {
+        self.extractors.register(extractor);
+    }
+
     /// Emit a progress event if a callback is registered.
     fn emit_progress(&self, event: ProgressEvent) {
         if let Some(ref callback) = self.progress_callback {
@@ -162,29 +212,24 @@ impl Indexer {
                 count: stats.files_scanned,
             });
 
-            // Check if we should index this file type
-            if !extract::should_index(path, &self.config) {
+            // Check if we should index this file type, or a registered
+            // extractor might claim it by content
+            let claimable = extract::should_index(path, &self.config)
+                || (self.extractors.has_registered() && !extract::is_excluded(path, &self.config));
+            if !claimable {
                 stats.files_skipped += 1;
                 continue;
             }
 
-            // Check file size
+            // Check file size (the limit applies to each member of an archive)
             let metadata = std::fs::metadata(path)?;
-            if metadata.len() > self.config.max_file_size {
+            if metadata.len() > self.config.max_file_size
+                && !extract::is_archive(path, &self.config)
+            {
                 stats.files_skipped += 1;
                 continue;
             }
 
-            // Calculate file hash
-            let content = match std::fs::read(path) {
-                Ok(c) => c,
-                Err(_) => {
-                    stats.files_skipped += 1;
-                    continue;
-                }
-            };
-            let hash = blake3::hash(&content).to_hex().to_string();
-
             // Get relative path
             let rel_path = path
                 .strip_prefix(&self.root)
@@ -192,6 +237,38 @@ impl Indexer {
                 .to_string_lossy()
                 .to_string();
 
+            // Calculate file hash, streaming archives which may be large
+            let hash = if extract::is_archive(path, &self.config) {
+                let mut hasher = blake3::Hasher::new();
+                let file = std::fs::File::open(path);
+                match file.and_then(|f| hasher.update_reader(f).map(|h| h.finalize())) {
+                    Ok(hash) => hash,
+                    Err(_) => {
+                        stats.files_skipped += 1;
+                        continue;
+                    }
+                }
+            } else {
+                let content = match std::fs::read(path) {
+                    Ok(c) => c,
+                    Err(_) => {
+                        stats.files_skipped += 1;
+                        continue;
+                    }
+                };
+
+                // Skip files no extractor claims, such as binary files,
+                // removing what an earlier version of the file indexed
+                if self.extractors.find(path, &content, &self.config).is_none() {
+                    self.remove_file(&rel_path)?;
+                    stats.files_skipped += 1;
+                    continue;
+                }
+
+                blake3::hash(&content)
+            };
+            let hash = hash.to_hex().to_string();
+
             // Check if file has changed
             if let Some(existing) = self.file_index.files.get(&rel_path) {
                 if existing.hash == hash {
@@ -200,9 +277,7 @@ impl Indexer {
                 }
 
                 // File changed - delete old chunks
-                let ids_to_delete: Vec<u64> =
-                    (existing.start_id..existing.start_id + existing.chunk_count as u64).collect();
-                self.storage.delete(ids_to_delete)?;
+                self.remove_file(&rel_path)?;
             }
 
             files_to_index.push((path.to_path_buf(), hash));
@@ -232,13 +307,26 @@ impl Indexer {
         // Save file index
         self.file_index.save(&self.root)?;
 
-        // Flush storage
+        // Build the search index of what changed, then flush storage
+        if total_files > 0 {
+            self.storage.optimize()?;
+        }
         self.storage.flush();
 
         Ok(stats)
     }
 
-    /// Index a single file.
+    /// Delete the chunks of an indexed file and its file index entry.
+    fn remove_file(&mut self, rel_path: &str) -> Result<()> {
+        if let Some(existing) = self.file_index.files.remove(rel_path) {
+            let ids_to_delete: Vec<u64> =
+                (existing.start_id..existing.start_id + existing.chunk_count as u64).collect();
+            self.storage.delete(ids_to_delete)?;
+        }
+        Ok(())
+    }
+
+    /// Index a single file, or each text member of an archive.
     fn index_file(&mut self, path: &Path, hash: &str) -> Result<usize> {
         let rel_path = path
             .strip_prefix(&self.root)
@@ -246,41 +334,136 @@ impl Indexer {
             .to_string_lossy()
             .to_string();
 
-        // Extract text
-        let text = extract::extract_text(path)?;
-        if text.is_empty() {
+        // Index the file, or the members of an archive under virtual paths
+        let start_id = self.file_index.next_id;
+        let mut chunk_count = 0;
+        let indexed = if extract::is_archive(path, &self.config) {
+            // Members are indexed as they are read, which needs `self` mutably
+            let config = self.config.clone();
+            extract::extract_members(path, &config, |member| {
+                let doc_path = format!("{}!/{}", rel_path, member.name);
+                let next_id = start_id + chunk_count as u64;
+                chunk_count += self.index_document(&doc_path, &member.content, hash, next_id)?;
+                Ok(())
+            })
+        } else {
+            let content = std::fs::read(path)?;
+            self.index_document(&rel_path, &content, hash, start_id)
+                .map(|count| chunk_count = count)
+        };
+
+        // Members indexed before a failure would be left without an entry
+        if let Err(e) = indexed {
+            if chunk_count > 0 {
+                let ids = (start_id..start_id + chunk_count as u64).collect();
+                self.storage.delete(ids)?;
+            }
+            return Err(e);
+        }
+        if chunk_count == 0 {
             return Ok(0);
         }
 
-        // Extract chunks using tree-sitter for code files, text chunking for others
-        let chunks = extract::extract_chunks(
-            path,
-            &text,
-            self.config.chunk_size,
-            self.config.chunk_overlap,
-            &mut self.parser,
+        // Update file index
+        self.file_index.files.insert(
+            rel_path,
+            FileMetadata {
+                hash: hash.to_string(),
+                mtime: std::fs::metadata(path)
+                    .map(|m| {
+                        m.modified()
+                            .ok()
+                            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                            .map(|d| d.as_secs())
+                            .unwrap_or(0)
+                    })
+                    .unwrap_or(0),
+                chunk_count,
+                start_id,
+            },
         );
+        self.file_index.next_id = start_id + chunk_count as u64;
+
+        Ok(chunk_count)
+    }
+
+    /// Extract, embed and store the chunks of one document, with point IDs
+    /// starting at `start_id`.
+    fn index_document(
+        &mut self,
+        rel_path: &str,
+        content: &[u8],
+        hash: &str,
+        start_id: u64,
+    ) -> Result<usize> {
+        let path = Path::new(rel_path);
+        let Some(extractor) = self.extractors.find(path, content, &self.config) else {
+            return Ok(0);
+        };
+
+        // Measure chunk sizes in the configured unit, with the default model
+        let embedder = &self.spaces[0].embedder;
+        let count_tokens = |s: &str| embedder.count_tokens(s);
+        let measure: extract::Measure = match self.config.chunk_unit {
+            ChunkUnit::Chars => &extract::count_chars,
+            ChunkUnit::Tokens => &count_tokens,
+        };
+
+        // Extract chunks with the extractor that claimed the file
+        let mut context = ExtractContext {
+            config: &self.config,
+            measure,
+            parser: &mut self.parser,
+        };
+        let chunks = extractor.extract(path, content, &mut context)?;
         if chunks.is_empty() {
             return Ok(0);
         }
 
-        // Generate embeddings
-        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
-        let embeddings = self.embedder.embed_batch(&texts)?;
+        // Warn about chunks a model will silently truncate, counting the text
+        // it embeds: the chunk with its context, in the document template
+        let embedding_texts: Vec<_> = chunks.iter().map(|c| c.embedding_text()).collect();
+        for space in &self.spaces {
+            let Some(max_tokens) = space.embedder.max_tokens() else {
+                continue;
+            };
+            for (chunk, text) in chunks.iter().zip(&embedding_texts) {
+                let tokens = space.embedder.count_tokens(&space.embedder.document(text));
+                if tokens > max_tokens {
+                    tracing::warn!(
+                        "{}:{}-{} is {} tokens and will be truncated to {} by {}",
+                        rel_path,
+                        chunk.start_line,
+                        chunk.end_line,
+                        tokens,
+                        max_tokens,
+                        space.embedder.model_id()
+                    );
+                }
+            }
+        }
+
+        // Generate embeddings, including each chunk's structural context
+        let texts: Vec<&str> = embedding_texts.iter().map(|t| t.as_ref()).collect();
+        let embeddings = embed::embed_spaces(&self.spaces, &texts)?;
 
         // Prepare points for storage
-        let start_id = self.file_index.next_id;
         let mut points = Vec::with_capacity(chunks.len());
 
         for (i, (chunk, embedding)) in chunks.iter().zip(embeddings.into_iter()).enumerate() {
             let point_id = start_id + i as u64;
             let payload = ChunkPayload {
-                path: rel_path.clone(),
+                path: rel_path.to_string(),
                 chunk_index: chunk.index,
                 start_line: chunk.start_line,
                 end_line: chunk.end_line,
                 text: chunk.text.clone(),
                 file_hash: hash.to_string(),
+                context: chunk.context.clone(),
+                page: chunk.page,
+                cell: chunk.cell,
+                location: chunk.location.clone(),
+                metadata: chunk.metadata.clone(),
             };
             points.push((point_id, embedding, payload));
         }
@@ -288,26 +471,6 @@ impl Indexer {
         // Store vectors
         self.storage.upsert(points)?;
 
-        // Update file index
-        self.file_index.files.insert(
-            rel_path,
-            FileMetadata {
-                hash: hash.to_string(),
-                mtime: std::fs::metadata(path)
-                    .map(|m| {
-                        m.modified()
-                            .ok()
-                            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                            .map(|d| d.as_secs())
-                            .unwrap_or(0)
-                    })
-                    .unwrap_or(0),
-                chunk_count: chunks.len(),
-                start_id,
-            },
-        );
-        self.file_index.next_id = start_id + chunks.len() as u64;
-
         Ok(chunks.len())
     }
 
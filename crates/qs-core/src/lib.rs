@@ -10,13 +10,17 @@ pub mod embed;
 pub mod extract;
 pub mod index;
 pub mod parse;
+pub mod reindex;
 pub mod search;
 pub mod storage;
 
 pub use config::Config;
 pub use consts::*;
 pub use discover::find_qs_root;
+pub use embed::EmbeddingBackend;
+pub use extract::{Chunk, ExtractContext, Extractor};
 pub use index::Indexer;
+pub use reindex::Reindexer;
 pub use search::Searcher;
 pub use storage::Storage;
 
@@ -37,9 +41,28 @@ pub enum QsError {
     #[error("Embedding error: {0}")]
     Embedding(String),
 
+    #[error(
+        "Index was built with {index_model} ({index_dimension} dimensions), but the configured model is {model} ({dimension} dimensions). Run `qs reindex` to rebuild it with the new model"
+    )]
+    ModelMismatch {
+        index_model: String,
+        index_dimension: usize,
+        model: String,
+        dimension: usize,
+    },
+
     #[error("Index error: {0}")]
     Index(String),
 
+    #[error("Query error: {0}")]
+    Query(String),
+
+    #[error("Grammar error: {0}")]
+    Grammar(String),
+
+    #[error("Extraction error: {0}")]
+    Extract(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
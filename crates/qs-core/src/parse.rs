@@ -2,15 +2,28 @@
 //!
 //! Extracts meaningful code units (functions, classes, structs, methods)
 //! as chunks for embedding.
+//!
+//! What becomes a chunk is decided by a tree-sitter query when one exists
+//! for the language (built in, or `.qs/queries/<lang>.scm`), and by a list
+//! of definition node kinds otherwise. Queries capture the chunk node as
+//! `@chunk`, and optionally its `@name` and the `@doc` comments above it.
+//!
+//! Besides the compiled-in languages, grammars can be loaded from shared
+//! libraries at runtime; those always chunk by query.
 
+use std::collections::HashMap;
+use std::ops::Range;
 use std::path::Path;
 
-use tree_sitter::{Language, Parser, Tree};
+use libloading::{Library, Symbol};
+use tree_sitter::{Language, Parser, Query, QueryCursor, StreamingIterator, Tree};
+use tree_sitter_language::LanguageFn;
 
 use crate::extract::Chunk;
+use crate::{QsError, Result};
 
 /// Supported programming languages for tree-sitter parsing.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CodeLanguage {
     #[cfg(feature = "rs")]
     Rust,
@@ -20,6 +33,8 @@ pub enum CodeLanguage {
     JavaScript,
     #[cfg(feature = "typescript")]
     TypeScript,
+    #[cfg(feature = "typescript")]
+    Tsx,
     #[cfg(feature = "go")]
     Go,
     #[cfg(feature = "java")]
@@ -28,6 +43,26 @@ pub enum CodeLanguage {
     C,
     #[cfg(feature = "cpp")]
     Cpp,
+    #[cfg(feature = "ruby")]
+    Ruby,
+    #[cfg(feature = "csharp")]
+    CSharp,
+    #[cfg(feature = "php")]
+    Php,
+    #[cfg(feature = "bash")]
+    Bash,
+    #[cfg(feature = "kotlin")]
+    Kotlin,
+    #[cfg(feature = "swift")]
+    Swift,
+    #[cfg(feature = "scala")]
+    Scala,
+    #[cfg(feature = "lua")]
+    Lua,
+    #[cfg(feature = "zig")]
+    Zig,
+    #[cfg(feature = "haskell")]
+    Haskell,
 }
 
 impl CodeLanguage {
@@ -41,7 +76,9 @@ impl CodeLanguage {
             #[cfg(feature = "javascript")]
             "js" | "jsx" | "mjs" | "cjs" => Some(Self::JavaScript),
             #[cfg(feature = "typescript")]
-            "ts" | "tsx" | "mts" | "cts" => Some(Self::TypeScript),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            #[cfg(feature = "typescript")]
+            "tsx" => Some(Self::Tsx),
             #[cfg(feature = "go")]
             "go" => Some(Self::Go),
             #[cfg(feature = "java")]
@@ -50,40 +87,230 @@ impl CodeLanguage {
             "c" | "h" => Some(Self::C),
             #[cfg(feature = "cpp")]
             "cpp" | "cc" | "cxx" | "hpp" | "hxx" | "hh" => Some(Self::Cpp),
+            #[cfg(feature = "ruby")]
+            "rb" | "rake" | "gemspec" => Some(Self::Ruby),
+            #[cfg(feature = "csharp")]
+            "cs" => Some(Self::CSharp),
+            #[cfg(feature = "php")]
+            "php" => Some(Self::Php),
+            #[cfg(feature = "bash")]
+            "sh" | "bash" => Some(Self::Bash),
+            #[cfg(feature = "kotlin")]
+            "kt" | "kts" => Some(Self::Kotlin),
+            #[cfg(feature = "swift")]
+            "swift" => Some(Self::Swift),
+            #[cfg(feature = "scala")]
+            "scala" | "sc" => Some(Self::Scala),
+            #[cfg(feature = "lua")]
+            "lua" => Some(Self::Lua),
+            #[cfg(feature = "zig")]
+            "zig" => Some(Self::Zig),
+            #[cfg(feature = "haskell")]
+            "hs" => Some(Self::Haskell),
+            _ => None,
+        }
+    }
+
+    /// Detect language from its name, as used for query files (e.g. `rust`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            #[cfg(feature = "rs")]
+            "rust" => Some(Self::Rust),
+            #[cfg(feature = "python")]
+            "python" => Some(Self::Python),
+            #[cfg(feature = "javascript")]
+            "javascript" => Some(Self::JavaScript),
+            #[cfg(feature = "typescript")]
+            "typescript" => Some(Self::TypeScript),
+            #[cfg(feature = "typescript")]
+            "tsx" => Some(Self::Tsx),
+            #[cfg(feature = "go")]
+            "go" => Some(Self::Go),
+            #[cfg(feature = "java")]
+            "java" => Some(Self::Java),
+            #[cfg(feature = "c")]
+            "c" => Some(Self::C),
+            #[cfg(feature = "cpp")]
+            "cpp" => Some(Self::Cpp),
+            #[cfg(feature = "ruby")]
+            "ruby" => Some(Self::Ruby),
+            #[cfg(feature = "csharp")]
+            "csharp" => Some(Self::CSharp),
+            #[cfg(feature = "php")]
+            "php" => Some(Self::Php),
+            #[cfg(feature = "bash")]
+            "bash" => Some(Self::Bash),
+            #[cfg(feature = "kotlin")]
+            "kotlin" => Some(Self::Kotlin),
+            #[cfg(feature = "swift")]
+            "swift" => Some(Self::Swift),
+            #[cfg(feature = "scala")]
+            "scala" => Some(Self::Scala),
+            #[cfg(feature = "lua")]
+            "lua" => Some(Self::Lua),
+            #[cfg(feature = "zig")]
+            "zig" => Some(Self::Zig),
+            #[cfg(feature = "haskell")]
+            "haskell" => Some(Self::Haskell),
+            _ => None,
+        }
+    }
+
+    /// Detect language from a file name without a telling extension (e.g. `Rakefile`).
+    pub fn from_filename(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "ruby")]
+            "Rakefile" | "Gemfile" | "Guardfile" | "Podfile" | "Vagrantfile" | "Brewfile"
+            | "Fastfile" => Some(Self::Ruby),
+            #[cfg(feature = "python")]
+            "SConstruct" | "SConscript" => Some(Self::Python),
+            #[cfg(feature = "bash")]
+            "PKGBUILD" | ".bashrc" | ".bash_profile" | ".zshrc" | ".profile" => Some(Self::Bash),
+            _ => None,
+        }
+    }
+
+    /// Detect language from a `#!` line, e.g. `#!/usr/bin/env python3`.
+    pub fn from_shebang(source: &str) -> Option<Self> {
+        let line = source.strip_prefix("#!")?.lines().next()?;
+        let mut words = line.split_whitespace();
+        let mut interpreter = words.next()?.rsplit('/').next()?;
+
+        // `env` runs the first word that is not an option or a variable
+        if interpreter == "env" {
+            interpreter = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+        }
+
+        // Drop the version, as in `python3.12`
+        match interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+            #[cfg(feature = "python")]
+            "python" | "pypy" => Some(Self::Python),
+            #[cfg(feature = "javascript")]
+            "node" | "nodejs" | "bun" => Some(Self::JavaScript),
+            #[cfg(feature = "typescript")]
+            "ts-node" => Some(Self::TypeScript),
+            #[cfg(feature = "ruby")]
+            "ruby" => Some(Self::Ruby),
+            #[cfg(feature = "php")]
+            "php" => Some(Self::Php),
+            #[cfg(feature = "bash")]
+            "sh" | "bash" | "zsh" | "dash" | "ksh" => Some(Self::Bash),
+            #[cfg(feature = "lua")]
+            "lua" | "luajit" => Some(Self::Lua),
             _ => None,
         }
     }
 
-    /// Get the tree-sitter language for this code language.
+    /// Detect language from a file's extension or, for files without one,
+    /// from the file name or shebang line.
+    pub fn detect(path: &Path, source: &str) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) => Self::from_extension(ext),
+            None => path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(Self::from_filename)
+                .or_else(|| Self::from_shebang(source)),
+        }
+    }
+
+    /// Name of this language, as used for query files (e.g. `.qs/queries/rust.scm`).
     #[allow(unreachable_patterns)]
-    fn tree_sitter_language(&self) -> Language {
+    pub fn name(&self) -> &'static str {
         match self {
             #[cfg(feature = "rs")]
-            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::Rust => "rust",
             #[cfg(feature = "python")]
-            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::Python => "python",
             #[cfg(feature = "javascript")]
-            Self::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Self::JavaScript => "javascript",
             #[cfg(feature = "typescript")]
-            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::TypeScript => "typescript",
+            #[cfg(feature = "typescript")]
+            Self::Tsx => "tsx",
             #[cfg(feature = "go")]
-            Self::Go => tree_sitter_go::LANGUAGE.into(),
+            Self::Go => "go",
             #[cfg(feature = "java")]
-            Self::Java => tree_sitter_java::LANGUAGE.into(),
+            Self::Java => "java",
             #[cfg(feature = "c")]
-            Self::C => tree_sitter_c::LANGUAGE.into(),
+            Self::C => "c",
             #[cfg(feature = "cpp")]
-            Self::Cpp => tree_sitter_cpp::LANGUAGE.into(),
-            _ => panic!(
-                "no tree sitter language installed for this variant. Please install with a feature (e.g. `cargo install qs-core --features rs`)"
-            ),
+            Self::Cpp => "cpp",
+            #[cfg(feature = "ruby")]
+            Self::Ruby => "ruby",
+            #[cfg(feature = "csharp")]
+            Self::CSharp => "csharp",
+            #[cfg(feature = "php")]
+            Self::Php => "php",
+            #[cfg(feature = "bash")]
+            Self::Bash => "bash",
+            #[cfg(feature = "kotlin")]
+            Self::Kotlin => "kotlin",
+            #[cfg(feature = "swift")]
+            Self::Swift => "swift",
+            #[cfg(feature = "scala")]
+            Self::Scala => "scala",
+            #[cfg(feature = "lua")]
+            Self::Lua => "lua",
+            #[cfg(feature = "zig")]
+            Self::Zig => "zig",
+            #[cfg(feature = "haskell")]
+            Self::Haskell => "haskell",
+            _ => unreachable!(),
         }
     }
 
-    /// Get the node kinds that represent top-level definitions we want to extract.
+    /// Get the tree-sitter language for this code language, if its grammar
+    /// is compiled in.
     #[allow(unreachable_patterns)]
-    fn definition_kinds(&self) -> &[&str] {
+    fn tree_sitter_language(&self) -> Option<Language> {
         match self {
+            #[cfg(feature = "rs")]
+            Self::Rust => Some(tree_sitter_rust::LANGUAGE.into()),
+            #[cfg(feature = "python")]
+            Self::Python => Some(tree_sitter_python::LANGUAGE.into()),
+            #[cfg(feature = "javascript")]
+            Self::JavaScript => Some(tree_sitter_javascript::LANGUAGE.into()),
+            #[cfg(feature = "typescript")]
+            Self::TypeScript => Some(tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into()),
+            #[cfg(feature = "typescript")]
+            Self::Tsx => Some(tree_sitter_typescript::LANGUAGE_TSX.into()),
+            #[cfg(feature = "go")]
+            Self::Go => Some(tree_sitter_go::LANGUAGE.into()),
+            #[cfg(feature = "java")]
+            Self::Java => Some(tree_sitter_java::LANGUAGE.into()),
+            #[cfg(feature = "c")]
+            Self::C => Some(tree_sitter_c::LANGUAGE.into()),
+            #[cfg(feature = "cpp")]
+            Self::Cpp => Some(tree_sitter_cpp::LANGUAGE.into()),
+            #[cfg(feature = "ruby")]
+            Self::Ruby => Some(tree_sitter_ruby::LANGUAGE.into()),
+            #[cfg(feature = "csharp")]
+            Self::CSharp => Some(tree_sitter_c_sharp::LANGUAGE.into()),
+            #[cfg(feature = "php")]
+            Self::Php => Some(tree_sitter_php::LANGUAGE_PHP.into()),
+            #[cfg(feature = "bash")]
+            Self::Bash => Some(tree_sitter_bash::LANGUAGE.into()),
+            #[cfg(feature = "kotlin")]
+            Self::Kotlin => Some(tree_sitter_kotlin_ng::LANGUAGE.into()),
+            #[cfg(feature = "swift")]
+            Self::Swift => Some(tree_sitter_swift::LANGUAGE.into()),
+            #[cfg(feature = "scala")]
+            Self::Scala => Some(tree_sitter_scala::LANGUAGE.into()),
+            #[cfg(feature = "lua")]
+            Self::Lua => Some(tree_sitter_lua::LANGUAGE.into()),
+            #[cfg(feature = "zig")]
+            Self::Zig => Some(tree_sitter_zig::LANGUAGE.into()),
+            #[cfg(feature = "haskell")]
+            Self::Haskell => Some(tree_sitter_haskell::LANGUAGE.into()),
+            _ => None,
+        }
+    }
+
+    /// Get the node kinds that represent top-level definitions we want to extract.
+    #[allow(unreachable_patterns)]
+    fn definition_kinds(&self) -> Option<&'static [&'static str]> {
+        let kinds: &[&str] = match self {
             #[cfg(feature = "rs")]
             Self::Rust => &[
                 "function_item",
@@ -103,16 +330,10 @@ impl CodeLanguage {
                 "class_definition",
                 "decorated_definition",
             ],
-            #[cfg(any(feature = "javascript", feature = "typescript"))]
-            Self::JavaScript | Self::TypeScript => &[
-                "function_declaration",
-                "class_declaration",
-                "method_definition",
-                "arrow_function",
-                "function",
-                "export_statement",
-                "lexical_declaration",
-            ],
+            #[cfg(feature = "javascript")]
+            Self::JavaScript => JS_DEFINITION_KINDS,
+            #[cfg(feature = "typescript")]
+            Self::TypeScript | Self::Tsx => JS_DEFINITION_KINDS,
             #[cfg(feature = "go")]
             Self::Go => &[
                 "function_declaration",
@@ -137,16 +358,124 @@ impl CodeLanguage {
                 "class_specifier",
                 "namespace_definition",
             ],
-            _ => panic!(
-                "no tree sitter language installed for this variant. Please install with a feature (e.g. `cargo install qs-core --features rs`)"
-            ),
+            #[cfg(feature = "ruby")]
+            Self::Ruby => &[
+                "method",
+                "singleton_method",
+                "class",
+                "module",
+                "singleton_class",
+            ],
+            #[cfg(feature = "csharp")]
+            Self::CSharp => &[
+                "class_declaration",
+                "struct_declaration",
+                "interface_declaration",
+                "enum_declaration",
+                "record_declaration",
+                "delegate_declaration",
+                "method_declaration",
+                "constructor_declaration",
+            ],
+            #[cfg(feature = "php")]
+            Self::Php => &[
+                "function_definition",
+                "class_declaration",
+                "interface_declaration",
+                "trait_declaration",
+                "enum_declaration",
+                "method_declaration",
+            ],
+            #[cfg(feature = "bash")]
+            Self::Bash => &["function_definition"],
+            #[cfg(feature = "kotlin")]
+            Self::Kotlin => &[
+                "class_declaration",
+                "object_declaration",
+                "function_declaration",
+                "type_alias",
+            ],
+            #[cfg(feature = "swift")]
+            Self::Swift => &[
+                "class_declaration",
+                "protocol_declaration",
+                "function_declaration",
+                "typealias_declaration",
+            ],
+            #[cfg(feature = "scala")]
+            Self::Scala => &[
+                "class_definition",
+                "object_definition",
+                "trait_definition",
+                "enum_definition",
+                "function_definition",
+            ],
+            #[cfg(feature = "lua")]
+            Self::Lua => &["function_declaration"],
+            #[cfg(feature = "zig")]
+            Self::Zig => &["function_declaration", "test_declaration"],
+            #[cfg(feature = "haskell")]
+            Self::Haskell => &[
+                "function",
+                "bind",
+                "data_type",
+                "newtype",
+                "type_synomym",
+                "class",
+                "instance",
+            ],
+            _ => &[],
+        };
+        Some(kinds).filter(|kinds| !kinds.is_empty())
+    }
+
+    /// Get the built-in chunk query, for languages that ship one.
+    #[allow(unreachable_patterns)]
+    fn builtin_query(&self) -> Option<&'static str> {
+        match self {
+            #[cfg(feature = "rs")]
+            Self::Rust => Some(include_str!("../queries/rust.scm")),
+            #[cfg(feature = "go")]
+            Self::Go => Some(include_str!("../queries/go.scm")),
+            #[cfg(feature = "zig")]
+            Self::Zig => Some(include_str!("../queries/zig.scm")),
+            #[cfg(feature = "haskell")]
+            Self::Haskell => Some(include_str!("../queries/haskell.scm")),
+            _ => None,
         }
     }
 }
 
+/// Definition node kinds shared by the JavaScript, TypeScript and TSX grammars.
+#[cfg(any(feature = "javascript", feature = "typescript"))]
+const JS_DEFINITION_KINDS: &[&str] = &[
+    "function_declaration",
+    "class_declaration",
+    "method_definition",
+    "arrow_function",
+    "function",
+    "export_statement",
+    "lexical_declaration",
+];
+
 /// Code parser using tree-sitter.
 pub struct CodeParser {
     parser: Parser,
+    /// Compiled chunk queries by language; user queries replace built-in ones
+    queries: HashMap<CodeLanguage, Query>,
+    /// Grammars loaded at runtime
+    grammars: Vec<RuntimeGrammar>,
+    /// Lowercase file extension -> index into `grammars`
+    grammar_extensions: HashMap<String, usize>,
+    /// Shared libraries backing `grammars`; declared last so they are dropped last
+    libraries: Vec<Library>,
+}
+
+/// A tree-sitter grammar loaded from a shared library.
+struct RuntimeGrammar {
+    name: String,
+    language: Language,
+    query: Query,
 }
 
 impl CodeParser {
@@ -154,24 +483,202 @@ impl CodeParser {
     pub fn new() -> Self {
         Self {
             parser: Parser::new(),
+            queries: HashMap::new(),
+            grammars: Vec::new(),
+            grammar_extensions: HashMap::new(),
+            libraries: Vec::new(),
+        }
+    }
+
+    /// Set the chunk query for a language, replacing any built-in one.
+    ///
+    /// The query must capture at least `@chunk`.
+    pub fn set_query(&mut self, lang: CodeLanguage, source: &str) -> Result<()> {
+        let language = lang.tree_sitter_language().ok_or_else(|| {
+            QsError::Query(format!("{}: grammar is not compiled in", lang.name()))
+        })?;
+        let query = compile_query(&language, lang.name(), source)?;
+        self.queries.insert(lang, query);
+        Ok(())
+    }
+
+    /// Load a grammar from a shared library and use it for the given extensions.
+    ///
+    /// The library must export `tree_sitter_<name>` (with `-` replaced by `_`),
+    /// as `tree-sitter build` produces. Runtime grammars have no built-in
+    /// notion of definitions, so `query` decides what becomes a chunk.
+    /// Extensions of compiled-in languages are taken over by the grammar.
+    ///
+    /// Loading a library runs its initialization code, so only load trusted grammars.
+    pub fn load_grammar(
+        &mut self,
+        name: &str,
+        library: &Path,
+        query: &str,
+        extensions: &[String],
+    ) -> Result<()> {
+        let grammar_error =
+            |e: &dyn std::fmt::Display| QsError::Grammar(format!("{}: {}", name, e));
+        let symbol = format!("tree_sitter_{}", name.replace('-', "_"));
+
+        // SAFETY: the library is a tree-sitter grammar, whose language function
+        // takes no arguments and returns a pointer to static language data.
+        // The library is kept alive as long as the parser that uses it.
+        let (lib, language) = unsafe {
+            let lib = Library::new(library).map_err(|e| grammar_error(&e))?;
+            let func: Symbol<unsafe extern "C" fn() -> *const ()> =
+                lib.get(symbol.as_bytes()).map_err(|e| grammar_error(&e))?;
+            let language = Language::new(LanguageFn::from_raw(*func));
+            (lib, language)
+        };
+
+        self.add_grammar(name, language, query, extensions)?;
+        self.libraries.push(lib);
+        Ok(())
+    }
+
+    /// Use a grammar for the given extensions, chunking by `query`.
+    fn add_grammar(
+        &mut self,
+        name: &str,
+        language: Language,
+        query: &str,
+        extensions: &[String],
+    ) -> Result<()> {
+        // Rejects grammars built for an incompatible tree-sitter ABI
+        self.parser
+            .set_language(&language)
+            .map_err(|e| QsError::Grammar(format!("{}: {}", name, e)))?;
+        let query = compile_query(&language, name, query)?;
+
+        let index = self.grammars.len();
+        for ext in extensions {
+            self.grammar_extensions.insert(ext.to_lowercase(), index);
+        }
+        self.grammars.push(RuntimeGrammar {
+            name: name.to_string(),
+            language,
+            query,
+        });
+        Ok(())
+    }
+
+    /// Check if files with this extension can be parsed.
+    pub fn supports_extension(&self, ext: &str) -> bool {
+        self.grammar_extensions.contains_key(&ext.to_lowercase())
+            || CodeLanguage::from_extension(ext).is_some()
+    }
+
+    /// Load `<lang>.scm` query files from a directory.
+    ///
+    /// A missing directory is not an error. Files for languages that are
+    /// unknown or not compiled in are skipped with a warning.
+    pub fn load_queries(&mut self, dir: &Path) -> Result<()> {
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("scm") {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+
+            // Runtime grammars load their own queries
+            if self.grammars.iter().any(|g| g.name == stem) {
+                continue;
+            }
+
+            match CodeLanguage::from_name(stem) {
+                Some(lang) => self.set_query(lang, &std::fs::read_to_string(&path)?)?,
+                None => tracing::warn!("Skipping query for unknown language: {}", path.display()),
+            }
         }
+
+        Ok(())
     }
 
     /// Parse a file and extract semantic chunks.
     ///
-    /// Returns `None` if the language is not supported or parsing fails.
+    /// The language is detected with [`CodeLanguage::detect`], unless a
+    /// runtime grammar handles the extension. Returns `None` if the language
+    /// is not supported or parsing fails.
     pub fn parse_file(&mut self, path: &Path, source: &str) -> Option<Vec<Chunk>> {
-        let ext = path.extension()?.to_str()?;
-        let lang = CodeLanguage::from_extension(ext)?;
+        let grammar = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(|ext| self.grammar_extensions.get(&ext.to_lowercase()));
+
+        if let Some(&index) = grammar {
+            let grammar = &self.grammars[index];
+            self.parser.set_language(&grammar.language).ok()?;
+            let tree = self.parser.parse(source, None)?;
+            let chunks = extract_query_chunks(&tree, source, &grammar.query);
+            return Some(with_fallback(chunks, source));
+        }
+
+        let lang = CodeLanguage::detect(path, source)?;
+        self.parse_source(lang, source)
+    }
 
+    /// Parse source code in a known language and extract semantic chunks.
+    ///
+    /// Returns `None` if the grammar is not compiled in or parsing fails, so
+    /// callers can fall back to text chunking.
+    pub fn parse_source(&mut self, lang: CodeLanguage, source: &str) -> Option<Vec<Chunk>> {
         self.parser
-            .set_language(&lang.tree_sitter_language())
+            .set_language(&lang.tree_sitter_language()?)
             .ok()?;
 
         let tree = self.parser.parse(source, None)?;
 
-        Some(extract_chunks(&tree, source, lang))
+        // Built-in queries are compiled on first use, unless overridden
+        let builtin = lang
+            .builtin_query()
+            .filter(|_| !self.queries.contains_key(&lang));
+        if let Some(Err(e)) = builtin.map(|query| self.set_query(lang, query)) {
+            tracing::warn!("Built-in query failed to compile: {}", e);
+        }
+
+        let chunks = match self.queries.get(&lang) {
+            Some(query) => extract_query_chunks(&tree, source, query),
+            None => extract_chunks(&tree, source, lang.definition_kinds()?),
+        };
+
+        Some(with_fallback(chunks, source))
+    }
+}
+
+/// Compile a chunk query, which must capture at least `@chunk`.
+fn compile_query(language: &Language, name: &str, source: &str) -> Result<Query> {
+    let query =
+        Query::new(language, source).map_err(|e| QsError::Query(format!("{}: {}", name, e)))?;
+
+    if query.capture_index_for_name("chunk").is_none() {
+        return Err(QsError::Query(format!(
+            "{}: query has no @chunk capture",
+            name
+        )));
+    }
+
+    Ok(query)
+}
+
+/// If nothing matched, fall back to treating the whole file as one chunk.
+fn with_fallback(mut chunks: Vec<Chunk>, source: &str) -> Vec<Chunk> {
+    if chunks.is_empty() && !source.trim().is_empty() {
+        chunks.push(Chunk {
+            text: source.to_string(),
+            start_line: 1,
+            end_line: source.matches('\n').count() + 1,
+            index: 0,
+            ..Default::default()
+        });
     }
+    chunks
 }
 
 impl Default for CodeParser {
@@ -180,14 +687,109 @@ impl Default for CodeParser {
     }
 }
 
+/// A `<script>` element embedded in a single-file component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptBlock {
+    /// Byte range of the whole element, including its tags
+    pub element: Range<usize>,
+    /// Byte range of the script content between the tags
+    pub content: Range<usize>,
+    /// Language of the script, from its `lang` attribute (JavaScript if absent)
+    pub lang: Option<CodeLanguage>,
+}
+
+/// Check if a file extension is a single-file component format (Vue, Svelte).
+pub fn is_component_extension(ext: &str) -> bool {
+    matches!(ext.to_lowercase().as_str(), "vue" | "svelte")
+}
+
+/// Find the `<script>` blocks of a single-file component.
+///
+/// `lang` is `None` when the script's language isn't compiled in.
+pub fn script_blocks(source: &str) -> Vec<ScriptBlock> {
+    // ASCII lowercasing keeps byte offsets intact
+    let lower = source.to_ascii_lowercase();
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+
+    while let Some(found) = lower[pos..].find("<script") {
+        let start = pos + found;
+        let after_name = start + "<script".len();
+        pos = after_name;
+
+        // Skip tags that merely start with "script", like <scripts>
+        if !lower[after_name..].starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            continue;
+        }
+        let Some(tag_end) = lower[after_name..].find('>').map(|p| after_name + p + 1) else {
+            break;
+        };
+        let (content_end, element_end) = match lower[tag_end..].find("</script") {
+            Some(p) => {
+                let close = tag_end + p;
+                let end = lower[close..]
+                    .find('>')
+                    .map_or(source.len(), |q| close + q + 1);
+                (close, end)
+            }
+            None => (source.len(), source.len()),
+        };
+
+        let attrs = &source[after_name..tag_end - 1];
+        let lang = match attribute(attrs, "lang").as_deref() {
+            None | Some("js" | "javascript") => CodeLanguage::from_extension("js"),
+            Some("typescript") => CodeLanguage::from_extension("ts"),
+            Some(other) => CodeLanguage::from_extension(other),
+        };
+
+        blocks.push(ScriptBlock {
+            element: start..element_end,
+            content: tag_end..content_end,
+            lang,
+        });
+        pos = element_end;
+    }
+
+    blocks
+}
+
+/// Read an attribute value from the inside of an HTML start tag.
+fn attribute(attrs: &str, name: &str) -> Option<String> {
+    let lower = attrs.to_ascii_lowercase();
+    let mut search = 0;
+
+    while let Some(found) = lower[search..].find(name) {
+        let at = search + found;
+        search = at + name.len();
+
+        // Must be a whole attribute name followed by `=`
+        let preceded = lower[..at]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+        let rest = lower[search..].trim_start();
+        if !preceded || !rest.starts_with('=') {
+            continue;
+        }
+
+        let value = rest[1..].trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value
+                .split(|c: char| c.is_whitespace() || c == '>')
+                .next()
+                .unwrap_or_default(),
+        };
+        return Some(value.to_lowercase());
+    }
+
+    None
+}
+
 /// Extract chunks from a parsed syntax tree.
-fn extract_chunks(tree: &Tree, source: &str, lang: CodeLanguage) -> Vec<Chunk> {
+fn extract_chunks(tree: &Tree, source: &str, definition_kinds: &[&str]) -> Vec<Chunk> {
     let mut chunks = Vec::new();
 
-    // Get the kinds of definitions to extract
-    // for each language
-    let definition_kinds = lang.definition_kinds();
-
     let root = tree.root_node();
     let mut cursor = root.walk();
 
@@ -211,6 +813,7 @@ fn extract_chunks(tree: &Tree, source: &str, lang: CodeLanguage) -> Vec<Chunk> {
                 start_line,
                 end_line,
                 index: chunks.len(),
+                ..Default::default()
             });
         }
     }
@@ -221,17 +824,111 @@ fn extract_chunks(tree: &Tree, source: &str, lang: CodeLanguage) -> Vec<Chunk> {
         extract_chunks_recursive(&root, source, definition_kinds, &mut chunks);
     }
 
-    // If still no chunks, fall back to treating the whole file as one chunk
-    if chunks.is_empty() && !source.trim().is_empty() {
-        chunks.push(Chunk {
-            text: source.to_string(),
-            start_line: 1,
-            end_line: source.matches('\n').count() + 1,
-            index: 0,
+    chunks
+}
+
+/// Extract chunks from the `@chunk` captures of a query.
+///
+/// Only the outermost captures become chunks, so methods inside a captured
+/// class stay part of it. `@doc` captures extend a chunk upwards while they
+/// are directly above it, and `@name` becomes the chunk's context.
+fn extract_query_chunks(tree: &Tree, source: &str, query: &Query) -> Vec<Chunk> {
+    struct Found {
+        /// Byte range of the `@chunk` node
+        node: Range<usize>,
+        /// Start byte and row, including attached docs
+        start: usize,
+        start_row: usize,
+        end_row: usize,
+        name: Option<String>,
+    }
+
+    let Some(chunk_index) = query.capture_index_for_name("chunk") else {
+        return Vec::new();
+    };
+    let name_index = query.capture_index_for_name("name");
+    let doc_index = query.capture_index_for_name("doc");
+
+    let mut found = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(query, tree.root_node(), source.as_bytes());
+
+    while let Some(m) = matches.next() {
+        let Some(node) = m.captures.iter().find(|c| c.index == chunk_index) else {
+            continue;
+        };
+        let node = node.node;
+
+        let name = m
+            .captures
+            .iter()
+            .find(|c| Some(c.index) == name_index)
+            .and_then(|c| c.node.utf8_text(source.as_bytes()).ok())
+            .map(str::to_string);
+
+        // Walk docs upwards from the chunk, stopping at the first gap
+        let mut docs: Vec<_> = m
+            .captures
+            .iter()
+            .filter(|c| Some(c.index) == doc_index && c.node.end_byte() <= node.start_byte())
+            .map(|c| c.node)
+            .collect();
+        docs.sort_by_key(|doc| std::cmp::Reverse(doc.start_byte()));
+
+        let mut start = node.start_byte();
+        let mut start_row = node.start_position().row;
+        for doc in docs {
+            if doc.end_byte() > start || doc.end_position().row + 1 < start_row {
+                break;
+            }
+            start = doc.start_byte();
+            start_row = doc.start_position().row;
+        }
+
+        found.push(Found {
+            node: node.byte_range(),
+            start,
+            start_row,
+            end_row: node.end_position().row,
+            name,
         });
     }
 
-    chunks
+    // Outermost first, then the match with the most docs attached
+    found.sort_by_key(|f| (f.node.start, std::cmp::Reverse(f.node.end), f.start));
+
+    let mut kept: Vec<Found> = Vec::new();
+    for f in found {
+        match kept.last_mut() {
+            Some(last) if last.node == f.node => {
+                if last.name.is_none() {
+                    last.name = f.name;
+                }
+            }
+            Some(last) if f.node.start < last.node.end => {}
+            Some(last) if f.start < last.node.end => {
+                // Docs can't reach back into the previous chunk
+                kept.push(Found {
+                    start: f.node.start,
+                    start_row: source[..f.node.start].matches('\n').count(),
+                    ..f
+                });
+            }
+            _ => kept.push(f),
+        }
+    }
+
+    kept.into_iter()
+        .enumerate()
+        .map(|(index, f)| Chunk {
+            text: source[f.start..f.node.end].to_string(),
+            start_line: f.start_row + 1,
+            end_line: f.end_row + 1,
+            index,
+            context: f.name,
+            ..Default::default()
+        })
+        .collect()
 }
 
 /// Recursively extract chunks from nested definitions.
@@ -259,6 +956,7 @@ fn extract_chunks_recursive(
                 start_line,
                 end_line,
                 index: chunks.len(),
+                ..Default::default()
             });
         } else {
             // Recurse into children
@@ -282,9 +980,56 @@ mod tests {
             CodeLanguage::from_extension("ts"),
             Some(CodeLanguage::TypeScript)
         );
+        assert_eq!(CodeLanguage::from_extension("tsx"), Some(CodeLanguage::Tsx));
         assert_eq!(CodeLanguage::from_extension("txt"), None);
     }
 
+    #[test]
+    fn test_detect_without_extension() {
+        let detect = |path: &str, source: &str| CodeLanguage::detect(Path::new(path), source);
+
+        assert_eq!(
+            detect(
+                "bin/deploy",
+                "#!/usr/bin/env python3
+import sys
+"
+            ),
+            Some(CodeLanguage::Python)
+        );
+        assert_eq!(
+            detect(
+                "bin/serve",
+                "#!/usr/bin/env -S node --no-warnings
+"
+            ),
+            Some(CodeLanguage::JavaScript)
+        );
+        assert_eq!(
+            detect(
+                "configure",
+                "#!/bin/sh
+"
+            ),
+            Some(CodeLanguage::Bash)
+        );
+        assert_eq!(
+            detect("Rakefile", "task :default\n"),
+            Some(CodeLanguage::Ruby)
+        );
+        assert_eq!(detect("Makefile", "all:\n\tcc main.c\n"), None);
+
+        // Shebangs only matter for files without an extension
+        assert_eq!(
+            detect(
+                "notes.txt",
+                "#!/bin/sh
+"
+            ),
+            None
+        );
+    }
+
     #[test]
     fn test_parse_rust() {
         let source = r#"
@@ -329,4 +1074,462 @@ class Foo:
 
         assert_eq!(chunks.len(), 2); // def, class
     }
+
+    #[test]
+    fn test_rust_query_attaches_docs() {
+        let source = r#"// Unrelated note
+
+/// A point.
+#[derive(Debug)]
+struct Point {
+    x: i32,
+}
+
+impl Point {
+    /// Origin.
+    fn origin() -> Self {
+        Self { x: 0 }
+    }
+}
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.rs"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 2); // struct, impl
+        assert!(chunks[0].text.starts_with("/// A point."));
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (3, 7));
+        assert_eq!(chunks[0].context.as_deref(), Some("Point"));
+        assert_eq!(chunks[1].start_line, 9);
+    }
+
+    #[test]
+    fn test_user_query() {
+        let source = r#"
+fn helper() {}
+
+#[test]
+fn test_helper() {
+    helper();
+}
+"#;
+
+        let mut parser = CodeParser::new();
+        parser
+            .set_query(
+                CodeLanguage::Rust,
+                r#"((function_item name: (identifier) @name) @chunk
+                    (#match? @name "^test_"))"#,
+            )
+            .unwrap();
+        let chunks = parser
+            .parse_file(Path::new("test.rs"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].context.as_deref(), Some("test_helper"));
+        assert_eq!(chunks[0].start_line, 5);
+    }
+
+    #[test]
+    fn test_invalid_query() {
+        let mut parser = CodeParser::new();
+        let no_chunk = parser.set_query(CodeLanguage::Rust, "(function_item) @fn");
+        assert!(matches!(no_chunk, Err(QsError::Query(_))));
+        let bad_kind = parser.set_query(CodeLanguage::Rust, "(no_such_node) @chunk");
+        assert!(matches!(bad_kind, Err(QsError::Query(_))));
+    }
+
+    #[test]
+    fn test_load_queries() {
+        let temp = std::env::temp_dir().join(format!("qs-test-queries-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(&temp).unwrap();
+        std::fs::write(temp.join("rust.scm"), "(struct_item) @chunk").unwrap();
+        std::fs::write(temp.join("cobol.scm"), "(paragraph) @chunk").unwrap();
+
+        let mut parser = CodeParser::new();
+        parser.load_queries(&temp).unwrap();
+        let chunks = parser
+            .parse_file(Path::new("test.rs"), "fn a() {}\nstruct B;\n")
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].text, "struct B;");
+
+        std::fs::remove_dir_all(&temp).unwrap();
+    }
+
+    #[test]
+    fn test_load_grammar_missing_library() {
+        let mut parser = CodeParser::new();
+        let result = parser.load_grammar(
+            "mydsl",
+            Path::new("/nonexistent/libtree-sitter-mydsl.so"),
+            "(_) @chunk",
+            &["mydsl".to_string()],
+        );
+
+        assert!(matches!(result, Err(QsError::Grammar(_))));
+        assert!(!parser.supports_extension("mydsl"));
+    }
+
+    #[cfg(feature = "rs")]
+    #[test]
+    fn test_grammar_extensions() {
+        // A compiled-in language stands in for one loaded from a library
+        let mut parser = CodeParser::new();
+        parser
+            .add_grammar(
+                "rusty",
+                tree_sitter_rust::LANGUAGE.into(),
+                "(struct_item name: (type_identifier) @name) @chunk",
+                &["RSX".to_string(), "rs".to_string()],
+            )
+            .unwrap();
+        assert!(parser.supports_extension("rsx"));
+
+        // Its query decides the chunks, also for the extension it took over
+        let source = "fn main() {}\n\nstruct Point;\n";
+        for path in ["view.rsx", "lib.rs"] {
+            let chunks = parser.parse_file(Path::new(path), source).unwrap();
+            assert_eq!(chunks.len(), 1);
+            assert_eq!(chunks[0].context.as_deref(), Some("Point"));
+        }
+
+        // Queries are checked against the grammar
+        let bad_query = parser.add_grammar(
+            "rusty",
+            tree_sitter_rust::LANGUAGE.into(),
+            "(no_such_node) @chunk",
+            &["rsy".to_string()],
+        );
+        assert!(matches!(bad_query, Err(QsError::Query(_))));
+        assert!(!parser.supports_extension("rsy"));
+    }
+
+    #[cfg(feature = "go")]
+    #[test]
+    fn test_parse_go() {
+        let source = r#"package demo
+
+// Hello greets.
+func Hello() {}
+
+type (
+	A struct{}
+	B int
+)
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.go"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 2); // func, type block
+        assert_eq!(chunks[0].start_line, 3);
+        assert_eq!(chunks[0].context.as_deref(), Some("Hello"));
+        assert_eq!(chunks[1].context.as_deref(), Some("A"));
+    }
+
+    #[cfg(feature = "ruby")]
+    #[test]
+    fn test_parse_ruby() {
+        let source = r#"
+def hello
+  puts "Hello"
+end
+
+class Foo
+  def initialize
+    @x = 0
+  end
+end
+
+module Bar
+end
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.rb"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 3); // def, class, module
+    }
+
+    #[cfg(feature = "csharp")]
+    #[test]
+    fn test_parse_csharp() {
+        let source = r#"
+using System;
+
+namespace Demo
+{
+    public class Foo
+    {
+        public void Hello() { }
+    }
+
+    public interface IBar { }
+}
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.cs"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 2); // class, interface (inside namespace)
+    }
+
+    #[cfg(feature = "php")]
+    #[test]
+    fn test_parse_php() {
+        let source = r#"<?php
+function hello() {
+    echo "Hello";
+}
+
+class Foo {
+    public function bar() {}
+}
+
+interface Baz {}
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.php"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 3); // function, class, interface
+    }
+
+    #[cfg(feature = "bash")]
+    #[test]
+    fn test_parse_bash() {
+        let source = r#"#!/bin/bash
+greet() {
+  echo "hi"
+}
+
+function cleanup {
+  rm -f /tmp/x
+}
+
+greet
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.sh"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 2); // greet, cleanup
+    }
+
+    #[cfg(feature = "kotlin")]
+    #[test]
+    fn test_parse_kotlin() {
+        let source = r#"
+package demo
+
+fun hello() {
+    println("Hello")
+}
+
+class Foo(val x: Int) {
+    fun bar() = x
+}
+
+object Registry
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.kt"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 3); // fun, class, object
+    }
+
+    #[cfg(feature = "swift")]
+    #[test]
+    fn test_parse_swift() {
+        let source = r#"
+import Foundation
+
+func hello() {
+    print("Hello")
+}
+
+struct Point {
+    var x: Int
+}
+
+protocol Shape {
+    func area() -> Double
+}
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.swift"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 3); // func, struct, protocol
+    }
+
+    #[cfg(feature = "scala")]
+    #[test]
+    fn test_parse_scala() {
+        let source = r#"
+object Main {
+  def main(args: Array[String]): Unit = println("Hello")
+}
+
+trait Shape
+
+class Circle(r: Double) extends Shape
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.scala"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 3); // object, trait, class
+    }
+
+    #[cfg(feature = "lua")]
+    #[test]
+    fn test_parse_lua() {
+        let source = r#"
+local M = {}
+
+local function helper(x)
+  return x * 2
+end
+
+function M.run()
+  return helper(1)
+end
+
+return M
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.lua"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 2); // helper, M.run
+    }
+
+    #[cfg(feature = "zig")]
+    #[test]
+    fn test_parse_zig() {
+        let source = r#"
+const std = @import("std");
+
+pub fn main() void {
+    std.debug.print("Hello\n", .{});
+}
+
+const Point = struct {
+    x: i32,
+};
+
+test "point" {}
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.zig"), source)
+            .expect("should parse");
+
+        // Imports and other constants are not definitions
+        assert_eq!(chunks.len(), 3); // main, Point, test
+        assert!(chunks.iter().all(|c| !c.text.contains("@import")));
+        assert_eq!(chunks[1].context.as_deref(), Some("Point"));
+    }
+
+    #[cfg(feature = "haskell")]
+    #[test]
+    fn test_parse_haskell() {
+        let source = r#"
+module Main where
+
+data Shape = Circle Double
+
+area :: Shape -> Double
+area (Circle r) = pi * r * r
+
+main :: IO ()
+main = print (area (Circle 1))
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.hs"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 3); // data, area, main
+
+        // Type signatures stay with their definitions
+        assert!(chunks[1].text.starts_with("area :: Shape -> Double"));
+        assert_eq!(chunks[1].context.as_deref(), Some("area"));
+    }
+
+    #[cfg(feature = "typescript")]
+    #[test]
+    fn test_parse_tsx() {
+        let source = r#"
+import React from "react";
+
+function Greeting({ name }: { name: string }): JSX.Element {
+    return <h1 className="title">Hello, {name}</h1>;
+}
+
+const App = () => <Greeting name="qs" />;
+"#;
+
+        let mut parser = CodeParser::new();
+        let chunks = parser
+            .parse_file(Path::new("test.tsx"), source)
+            .expect("should parse");
+
+        assert_eq!(chunks.len(), 2); // function, const
+        assert!(chunks[0].text.contains("<h1 className=\"title\">"));
+    }
+
+    #[test]
+    fn test_script_blocks() {
+        let source = r#"<template>
+  <div>{{ msg }}</div>
+</template>
+
+<script setup lang="ts">
+const msg: string = "hi";
+</script>
+
+<style scoped>
+div { color: red; }
+</style>
+"#;
+
+        let blocks = script_blocks(source);
+        assert_eq!(blocks.len(), 1);
+        assert!(source[blocks[0].element.clone()].starts_with("<script setup"));
+        assert!(source[blocks[0].element.clone()].ends_with("</script>"));
+        assert_eq!(
+            &source[blocks[0].content.clone()],
+            "\nconst msg: string = \"hi\";\n"
+        );
+        assert_eq!(blocks[0].lang, CodeLanguage::from_extension("ts"));
+    }
 }
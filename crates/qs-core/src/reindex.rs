@@ -0,0 +1,342 @@
+//! Reindexing: migrate the index to another embedding model
+//!
+//! The new shard is built next to the current one from the chunk text
+//! stored in its payloads, so files are not read or parsed again and search
+//! keeps working on the current shard until the new one replaces it.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    Config, DEFAULT_VECTOR, Result, Storage, discover,
+    embed::{self, EmbeddingBackend, VectorSpace},
+    index::{ProgressCallback, ProgressEvent},
+    storage::{self, ShardModel},
+};
+
+/// Number of chunks read and embedded at a time
+const BATCH_SIZE: usize = 64;
+
+/// Rebuilds the index of a qs repository with the model of a new config.
+pub struct Reindexer {
+    root: PathBuf,
+    config: Config,
+    spaces: Vec<VectorSpace>,
+    progress_callback: Option<ProgressCallback>,
+}
+
+/// Stats from a reindexing run.
+#[derive(Debug, Default)]
+pub struct ReindexStats {
+    pub old_model: String,
+    pub chunks_reindexed: usize,
+}
+
+impl Reindexer {
+    /// Create a reindexer that moves the index to the model of `config`,
+    /// which replaces the repository's config when done.
+    pub fn new(root: PathBuf, config: Config) -> Result<Self> {
+        let spaces = embed::vector_spaces(&config)?;
+        Ok(Self {
+            root,
+            config,
+            spaces,
+            progress_callback: None,
+        })
+    }
+
+    /// Create a reindexer that embeds chunks with the given backend,
+    /// filling the default vector only.
+    pub fn with_backend(
+        root: PathBuf,
+        config: Config,
+        embedder: Box<dyn EmbeddingBackend>,
+    ) -> Self {
+        let spaces = vec![VectorSpace {
+            name: DEFAULT_VECTOR.to_string(),
+            embedder,
+        }];
+        Self {
+            root,
+            config,
+            spaces,
+            progress_callback: None,
+        }
+    }
+
+    /// Set a callback to receive progress updates while embedding.
+    pub fn set_progress_callback(&mut self, callback: ProgressCallback) {
+        self.progress_callback = Some(callback);
+    }
+
+    /// Embed every stored chunk with the new model into a new shard, then
+    /// save the new config and swap the new shard in.
+    ///
+    /// Files indexed in the meantime are not carried over, so run it while
+    /// nothing else updates the index. An interrupted run is finished by
+    /// running it again.
+    pub fn run(&self) -> Result<ReindexStats> {
+        let shard_path = discover::shard_dir(&self.root);
+        let new_shard_path = discover::reindex_shard_dir(&self.root);
+        let old_shard_path = discover::old_shard_dir(&self.root);
+
+        // A run interrupted between the renames left the shard aside
+        if !shard_path.exists() && old_shard_path.exists() {
+            std::fs::rename(&old_shard_path, &shard_path)?;
+        }
+        // Other leftovers of an interrupted run are started over
+        for leftover in [&new_shard_path, &old_shard_path] {
+            if leftover.exists() {
+                std::fs::remove_dir_all(leftover)?;
+            }
+        }
+        let stats = self.build(&shard_path, &new_shard_path)?;
+
+        // The config goes first: if the swap is interrupted, the index no
+        // longer matches it and asks for `qs reindex`, which starts over
+        // with the new model
+        self.new_config().save(&self.root)?;
+
+        // Swap the shards: each rename is atomic, `Storage::open` refuses to
+        // create a shard in between, and the old shard is only deleted once
+        // the new one is in place
+        std::fs::rename(&shard_path, &old_shard_path)?;
+        std::fs::rename(&new_shard_path, &shard_path)?;
+        std::fs::remove_dir_all(&old_shard_path)?;
+
+        Ok(stats)
+    }
+
+    /// The config to save, with the dimensions of the new models.
+    fn new_config(&self) -> Config {
+        let mut config = self.config.clone();
+        for space in &self.spaces {
+            let dimension = space.embedder.dimension();
+            if space.name == DEFAULT_VECTOR {
+                config.dimension = dimension;
+            } else if let Some(vector) = config.vectors.iter_mut().find(|v| v.name == space.name) {
+                vector.dimension = dimension;
+            }
+        }
+        config
+    }
+
+    /// Embed the chunks of the shard at `shard_path` into a new shard at
+    /// `new_shard_path`.
+    fn build(&self, shard_path: &Path, new_shard_path: &Path) -> Result<ReindexStats> {
+        // Open the current shard with the models it was built with
+        let mut old_models = ShardModel::load(shard_path)?;
+        if old_models.is_empty() {
+            // Shards that predate the record only tell their dimension
+            let config = Config::load(&self.root)?;
+            let dimension = storage::segment_dimensions(shard_path)?
+                .get(DEFAULT_VECTOR)
+                .copied()
+                .unwrap_or(config.dimension);
+            let model = ShardModel {
+                model: config.model,
+                dimension,
+                document_template: None,
+            };
+            old_models.insert(DEFAULT_VECTOR.to_string(), model);
+        }
+        // The new shard is created with the storage settings of the new config
+        let storage_config = &self.config.storage;
+        let old_storage = Storage::open_at(shard_path, &old_models, storage_config)?;
+        let new_storage = Storage::open_at(
+            new_shard_path,
+            &embed::shard_models(&self.spaces),
+            storage_config,
+        )?;
+
+        let total = old_storage.count()?;
+        let mut stats = ReindexStats {
+            old_model: old_models
+                .remove(DEFAULT_VECTOR)
+                .map(|m| m.model)
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        let mut offset = None;
+        loop {
+            let (chunks, next_offset) = old_storage.scroll(offset, BATCH_SIZE)?;
+
+            let embedding_texts: Vec<_> = chunks.iter().map(|(_, c)| c.embedding_text()).collect();
+            let texts: Vec<&str> = embedding_texts.iter().map(|t| t.as_ref()).collect();
+            let embeddings = embed::embed_spaces(&self.spaces, &texts)?;
+
+            // Points keep their IDs, so files.json stays valid
+            stats.chunks_reindexed += chunks.len();
+            let points = chunks
+                .into_iter()
+                .zip(embeddings)
+                .map(|((id, payload), embedding)| (id, embedding, payload))
+                .collect();
+            new_storage.upsert(points)?;
+
+            if let Some(ref callback) = self.progress_callback {
+                callback(ProgressEvent::Embedding {
+                    current: stats.chunks_reindexed,
+                    total,
+                });
+            }
+
+            match next_offset {
+                Some(next) => offset = Some(next),
+                None => break,
+            }
+        }
+        new_storage.optimize()?;
+        new_storage.flush();
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TemplateConfig;
+    use crate::embed::templates::Templates;
+    use crate::embed::testing::LengthBackend;
+    use crate::storage::ShardModels;
+    use crate::{Indexer, QsError};
+
+    fn backend(model: &str, dimension: usize) -> Box<dyn EmbeddingBackend> {
+        Box::new(LengthBackend::new(model, dimension))
+    }
+
+    /// A backend for the model of a config, in its templates.
+    fn config_backend(config: &Config, dimension: usize) -> Box<dyn EmbeddingBackend> {
+        Box::new(LengthBackend {
+            templates: Templates::for_model(&config.model, &config.templates).unwrap(),
+            ..LengthBackend::new(&config.model, dimension)
+        })
+    }
+
+    fn models(model: &str, dimension: usize) -> ShardModels {
+        let model = ShardModel {
+            model: model.to_string(),
+            dimension,
+            document_template: None,
+        };
+        ShardModels::from([(DEFAULT_VECTOR.to_string(), model)])
+    }
+
+    fn open(root: &Path, models: &ShardModels) -> Result<Storage> {
+        Storage::open(root, models, &Default::default())
+    }
+
+    /// IDs, paths and texts of every stored chunk.
+    fn stored_chunks(storage: &Storage) -> Vec<(u64, String, String)> {
+        let mut chunks = Vec::new();
+        let mut offset = None;
+        loop {
+            let (page, next_offset) = storage.scroll(offset, 2).unwrap();
+            chunks.extend(page.into_iter().map(|(id, c)| (id, c.path, c.text)));
+            match next_offset {
+                Some(next) => offset = Some(next),
+                None => break,
+            }
+        }
+        chunks
+    }
+
+    #[test]
+    fn test_reindex() {
+        let root = std::env::temp_dir().join(format!("qs-test-reindex-{}", std::process::id()));
+        std::fs::create_dir_all(discover::qs_dir(&root)).unwrap();
+        std::fs::write(
+            root.join("notes.md"),
+            "# Setup\n\nInstall it.\n\n# Usage\n\nRun it.\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("todo.txt"), "Write the docs\n").unwrap();
+
+        let mut indexer = Indexer::with_backend(root.clone(), backend("model-a", 2)).unwrap();
+        indexer.index(None).unwrap();
+        drop(indexer);
+        let (models_a, models_b) = (models("model-a", 2), models("model-b", 3));
+        let before = stored_chunks(&open(&root, &models_a).unwrap());
+        assert!(before.len() >= 2);
+
+        let config = Config {
+            model: "model-b".to_string(),
+            ..Config::load(&root).unwrap()
+        };
+        let reindexer = Reindexer::with_backend(root.clone(), config, backend("model-b", 3));
+        let stats = reindexer.run().unwrap();
+        assert_eq!(stats.old_model, "model-a");
+        assert_eq!(stats.chunks_reindexed, before.len());
+
+        // Points keep their IDs and payloads, in a shard of the new model
+        let shard_path = discover::shard_dir(&root);
+        assert_eq!(ShardModel::load(&shard_path).unwrap(), models_b);
+        let storage = open(&root, &models_b).unwrap();
+        assert_eq!(stored_chunks(&storage), before);
+        let results = storage.search(DEFAULT_VECTOR, vec![1.0; 3], 10).unwrap();
+        assert_eq!(results.len(), before.len());
+        drop(storage);
+        assert!(matches!(
+            open(&root, &models_a),
+            Err(QsError::ModelMismatch { .. })
+        ));
+
+        // The config names the new model, with its dimension
+        let saved = Config::load(&root).unwrap();
+        assert_eq!((saved.model.as_str(), saved.dimension), ("model-b", 3));
+        assert!(!discover::reindex_shard_dir(&root).exists());
+        assert!(!discover::old_shard_dir(&root).exists());
+
+        // A swap interrupted between the renames leaves no shard to open,
+        // until reindexing again puts it back
+        std::fs::rename(&shard_path, discover::old_shard_dir(&root)).unwrap();
+        assert!(matches!(open(&root, &models_b), Err(QsError::Index(_))));
+        Reindexer::with_backend(root.clone(), saved, backend("model-b", 3))
+            .run()
+            .unwrap();
+        assert_eq!(stored_chunks(&open(&root, &models_b).unwrap()), before);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_reindex_model_templates() {
+        let root =
+            std::env::temp_dir().join(format!("qs-test-reindex-templates-{}", std::process::id()));
+        std::fs::create_dir_all(discover::qs_dir(&root)).unwrap();
+        std::fs::write(root.join("todo.txt"), "Write the docs\n").unwrap();
+        let config = Config {
+            model: "nomic-embed-text".to_string(),
+            templates: TemplateConfig {
+                query: None,
+                document: Some("search_document: {text}\n".to_string()),
+            },
+            ..Config::load(&root).unwrap()
+        };
+        config.save(&root).unwrap();
+
+        let mut indexer = Indexer::with_backend(root.clone(), config_backend(&config, 2)).unwrap();
+        indexer.index(None).unwrap();
+        drop(indexer);
+
+        // The new model is embedded in its own templates, not the override
+        // set for the previous one
+        let mut config = Config::load(&root).unwrap();
+        config.set_model("e5-base-v2".to_string());
+        let backend = config_backend(&config, 3);
+        Reindexer::with_backend(root.clone(), config, backend)
+            .run()
+            .unwrap();
+
+        let models = ShardModel::load(&discover::shard_dir(&root)).unwrap();
+        let model = &models[DEFAULT_VECTOR];
+        assert_eq!(model.model, "e5-base-v2");
+        assert_eq!(model.document_template.as_deref(), Some("passage: {text}"));
+        assert_eq!(
+            Config::load(&root).unwrap().templates,
+            TemplateConfig::default()
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
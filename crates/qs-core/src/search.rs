@@ -1,41 +1,203 @@
 //! Search functionality
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use crate::{Config, Result, Storage, embed::Embedder, storage::SearchResult};
+use crate::{
+    Config, DEFAULT_VECTOR, QsError, Result, Storage,
+    embed::{self, EmbeddingBackend, VectorSpace, cache, rerank::Reranker},
+    extract,
+    storage::SearchResult,
+};
+
+/// Rank constant of reciprocal rank fusion, damping the weight of top ranks
+const RRF_K: f32 = 60.0;
 
 /// Searcher for querying the index.
 pub struct Searcher {
-    embedder: Embedder,
+    spaces: Vec<VectorSpace>,
     storage: Storage,
+    reranker: Option<Reranker>,
 }
 
 impl Searcher {
     /// Create a new searcher for a qs repository.
     pub fn new(root: PathBuf) -> Result<Self> {
         let config = Config::load(&root)?;
-        let embedder = Embedder::new(&config)?;
-        let storage = Storage::open(&root, &config)?;
+        let spaces = embed::vector_spaces(&config)?;
+        let storage = Storage::open(&root, &embed::shard_models(&spaces), &config.storage)?;
+        let reranker = match &config.reranker {
+            Some(reranker) => Some(Reranker::new(reranker, &cache::cache_dir(&config))?),
+            None => None,
+        };
+
+        Ok(Self {
+            spaces,
+            storage,
+            reranker,
+        })
+    }
+
+    /// Create a searcher that embeds queries with the given backend instead
+    /// of the configured model, searching the default vector only.
+    pub fn with_backend(root: PathBuf, embedder: Box<dyn EmbeddingBackend>) -> Result<Self> {
+        let config = Config::load(&root)?;
+        let spaces = vec![VectorSpace {
+            name: DEFAULT_VECTOR.to_string(),
+            embedder,
+        }];
+        let storage = Storage::open(&root, &embed::shard_models(&spaces), &config.storage)?;
+
+        Ok(Self {
+            spaces,
+            storage,
+            reranker: None,
+        })
+    }
 
-        Ok(Self { embedder, storage })
+    /// Rerank the top results of searches with a cross-encoder.
+    pub fn set_reranker(&mut self, reranker: Reranker) {
+        self.reranker = Some(reranker);
+    }
+
+    /// Check if search results are reranked.
+    pub fn reranks(&self) -> bool {
+        self.reranker.is_some()
     }
 
     /// Search for chunks matching the query.
     pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
-        // Embed the query
-        let query_embedding = self.embedder.embed(query)?;
+        self.search_vectors(query, &[], limit)
+    }
 
-        // Search storage
-        self.storage.search(query_embedding, limit)
+    /// Search the named vectors for chunks matching the query, fusing the
+    /// results of several vectors by reciprocal rank. Searches the default
+    /// vector if none are named.
+    ///
+    /// With a reranker, more candidates are fetched and the best `limit` by
+    /// rerank score are returned.
+    pub fn search_vectors(
+        &self,
+        query: &str,
+        vectors: &[&str],
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let spaces = match vectors {
+            [] => vec![&self.spaces[0]],
+            names => names
+                .iter()
+                .map(|name| self.space(name))
+                .collect::<Result<_>>()?,
+        };
+
+        let candidates = match &self.reranker {
+            Some(reranker) => reranker.candidates(limit),
+            None => limit,
+        };
+        let mut results = Vec::with_capacity(spaces.len());
+        for space in &spaces {
+            let query_embedding = space.embedder.embed_query(query)?;
+            results.push(
+                self.storage
+                    .search(&space.name, query_embedding, candidates)?,
+            );
+        }
+        let results = match results.len() {
+            1 => results.remove(0),
+            _ => fuse(results, candidates),
+        };
+
+        match &self.reranker {
+            Some(reranker) => reranker.rerank(query, results, limit),
+            None => Ok(results),
+        }
     }
 
     /// Find chunks similar to a given file.
     pub fn similar(&self, file_path: &std::path::Path, limit: usize) -> Result<Vec<SearchResult>> {
         // Read and embed the file content
-        let content = std::fs::read_to_string(file_path)?;
-        let embedding = self.embedder.embed(&content)?;
+        let bytes = std::fs::read(file_path)?;
+        let space = &self.spaces[0];
+        let text = extract::encoding::decode(&bytes);
+        let embedding = space
+            .embedder
+            .embed_documents(&[&text])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| QsError::Embedding("No embedding generated".to_string()))?;
 
         // Search storage
-        self.storage.search(embedding, limit)
+        self.storage.search(&space.name, embedding, limit)
+    }
+
+    fn space(&self, name: &str) -> Result<&VectorSpace> {
+        self.spaces
+            .iter()
+            .find(|space| space.name == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = self.spaces.iter().map(|s| s.name.as_str()).collect();
+                QsError::Query(format!(
+                    "Unknown vector: {}. Configured: {}",
+                    name,
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+/// Merge ranked result lists by reciprocal rank fusion: each chunk scores
+/// the sum of `1 / (RRF_K + rank)` over the lists it appears in.
+fn fuse(results: Vec<Vec<SearchResult>>, limit: usize) -> Vec<SearchResult> {
+    let mut fused: HashMap<(String, usize), SearchResult> = HashMap::new();
+    for list in results {
+        for (rank, mut result) in list.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f32 + 1.0);
+            let key = (result.payload.path.clone(), result.payload.chunk_index);
+            fused
+                .entry(key)
+                .and_modify(|r| r.score += score)
+                .or_insert_with(|| {
+                    result.score = score;
+                    result
+                });
+        }
+    }
+
+    let mut fused: Vec<SearchResult> = fused.into_values().collect();
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+    fused.truncate(limit);
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::ChunkPayload;
+
+    fn result(path: &str, score: f32) -> SearchResult {
+        SearchResult {
+            score,
+            rerank_score: None,
+            payload: ChunkPayload {
+                path: path.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_fuse() {
+        let code = vec![
+            result("a.rs", 0.9),
+            result("b.rs", 0.8),
+            result("c.rs", 0.7),
+        ];
+        let prose = vec![result("c.rs", 0.6), result("d.md", 0.5)];
+
+        // c.rs is found by both, and ranks first
+        let fused = fuse(vec![code, prose], 2);
+        let paths: Vec<&str> = fused.iter().map(|r| r.payload.path.as_str()).collect();
+        assert_eq!(paths, ["c.rs", "a.rs"]);
+        assert!((fused[0].score - (1.0 / 63.0 + 1.0 / 61.0)).abs() < 1e-6);
     }
 }
@@ -1,14 +1,19 @@
 //! Qdrant Edge storage wrapper
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 
 use edge::EdgeShard;
 use segment::data_types::vectors::{NamedQuery, VectorInternal, VectorStructInternal};
 use segment::types::{
-    Distance, ExtendedPointId, Payload, PayloadStorageType, SegmentConfig, VectorDataConfig,
+    BinaryQuantization, BinaryQuantizationConfig, CompressionRatio, Distance, ExtendedPointId,
+    HnswConfig, Indexes, Payload, PayloadStorageType, ProductQuantization,
+    ProductQuantizationConfig, QuantizationConfig, QuantizationSearchParams, ScalarQuantization,
+    ScalarQuantizationConfig, ScalarType, SearchParams, SegmentConfig, VectorDataConfig,
     VectorStorageType, WithPayloadInterface, WithVector,
 };
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use shard::count::CountRequestInternal;
@@ -18,14 +23,26 @@ use shard::operations::point_ops::{
 };
 use shard::query::query_enum::QueryEnum;
 use shard::query::{ScoringQuery, ShardQueryRequest};
+use shard::scroll::ScrollRequestInternal;
 
-use crate::{Config, QsError, Result, discover};
+use crate::config::{self, Compression, Quantization, StorageConfig};
+use crate::{DEFAULT_VECTOR, QsError, Result, discover, extract};
 
-/// Vector name used in the shard
-const VECTOR_NAME: &str = "chunks";
+/// Size (in KB) of a segment below which search skips the HNSW graph, as
+/// in Qdrant
+const DEFAULT_FULL_SCAN_THRESHOLD: usize = 10_000;
+
+/// File in the shard directory recording the models that built it
+const SHARD_MODEL_FILE: &str = "model.json";
+
+/// Directory of a shard holding its segments
+const SEGMENTS_DIR: &str = "segments";
+
+/// File in a segment directory holding its config
+const SEGMENT_STATE_FILE: &str = "segment.json";
 
 /// Metadata stored with each vector.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ChunkPayload {
     /// Relative file path from repo root
     pub path: String,
@@ -39,6 +56,29 @@ pub struct ChunkPayload {
     pub text: String,
     /// File hash for change detection
     pub file_hash: String,
+    /// Structural context, such as a heading breadcrumb
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// Page number in paged documents such as PDFs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    /// Cell number in Jupyter notebooks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cell: Option<usize>,
+    /// Position within a document without meaningful lines (e.g. `slide 4`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// Extractor-specific fields
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, Value>,
+}
+
+impl ChunkPayload {
+    /// The text that was embedded for the chunk, as
+    /// [`Chunk::embedding_text`](crate::Chunk::embedding_text).
+    pub fn embedding_text(&self) -> Cow<'_, str> {
+        extract::with_context(self.context.as_deref(), &self.text)
+    }
 }
 
 /// A search result.
@@ -46,35 +86,217 @@ pub struct ChunkPayload {
 pub struct SearchResult {
     /// Score (similarity)
     pub score: f32,
+    /// Score of the cross-encoder, if the results were reranked
+    pub rerank_score: Option<f32>,
     /// The payload
     pub payload: ChunkPayload,
 }
 
+/// The embedding model a vector of a shard was built with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardModel {
+    /// Model identifier, as written in the config
+    pub model: String,
+    /// Dimension of the stored vectors
+    pub dimension: usize,
+    /// Template chunks were embedded in, if the model has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_template: Option<String>,
+}
+
+/// The models of a shard, by vector name.
+pub type ShardModels = BTreeMap<String, ShardModel>;
+
+/// Embeddings of a chunk, by vector name.
+pub type NamedVectors = HashMap<String, Vec<f32>>;
+
+/// A segment's state file, with the parts of its config `C` that are read.
+#[derive(Deserialize)]
+struct SegmentState<C> {
+    config: C,
+}
+
+/// The part of a segment config that tells its vector dimensions.
+#[derive(Deserialize)]
+struct SegmentVectors {
+    vector_data: HashMap<String, SegmentVector>,
+}
+
+#[derive(Deserialize)]
+struct SegmentVector {
+    size: usize,
+}
+
+/// Contents of the model file, which held a single model before shards had
+/// several vectors.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ModelFile {
+    Single(ShardModel),
+    Named(ShardModels),
+}
+
+impl ShardModel {
+    /// Load the models recorded in a shard directory (empty if none).
+    pub fn load(shard_path: &Path) -> Result<ShardModels> {
+        let path = shard_path.join(SHARD_MODEL_FILE);
+        if !path.exists() {
+            return Ok(ShardModels::new());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(match serde_json::from_str(&content)? {
+            ModelFile::Single(model) => ShardModels::from([(DEFAULT_VECTOR.to_string(), model)]),
+            ModelFile::Named(models) => models,
+        })
+    }
+
+    /// Save the models of a shard to its directory.
+    pub fn save(shard_path: &Path, models: &ShardModels) -> Result<()> {
+        let content = serde_json::to_string_pretty(models)?;
+        std::fs::write(shard_path.join(SHARD_MODEL_FILE), content)?;
+        Ok(())
+    }
+
+    /// Check that a shard has each vector, built with the same model,
+    /// recording the models of new shards.
+    ///
+    /// Shards that predate the record only tell the dimension of their
+    /// vectors, so that is all that is checked, and nothing is recorded.
+    fn check(shard_path: &Path, models: &ShardModels) -> Result<()> {
+        let recorded = Self::load(shard_path)?;
+        if recorded.is_empty() {
+            let dimensions = segment_dimensions(shard_path)?;
+            if dimensions.is_empty() {
+                return Self::save(shard_path, models);
+            }
+            for (name, model) in models {
+                match dimensions.get(name) {
+                    Some(&dimension) if dimension != model.dimension => {
+                        return Err(QsError::ModelMismatch {
+                            index_model: "an unrecorded model".to_string(),
+                            index_dimension: dimension,
+                            model: model.model.clone(),
+                            dimension: model.dimension,
+                        });
+                    }
+                    Some(_) => {}
+                    None => return Err(missing_vector(name)),
+                }
+            }
+            return Ok(());
+        }
+
+        for (name, model) in models {
+            match recorded.get(name) {
+                Some(index) if index.model != model.model || index.dimension != model.dimension => {
+                    return Err(QsError::ModelMismatch {
+                        index_model: index.model.clone(),
+                        index_dimension: index.dimension,
+                        model: model.model.clone(),
+                        dimension: model.dimension,
+                    });
+                }
+                Some(index) if index.document_template != model.document_template => {
+                    let template = |t: &Option<String>| match t {
+                        Some(t) => format!("{:?}", t),
+                        None => "none".to_string(),
+                    };
+                    return Err(QsError::Index(format!(
+                        "vector {} was built with the document template {}, but the configured one is {}. Run `qs reindex` to rebuild it",
+                        name,
+                        template(&index.document_template),
+                        template(&model.document_template)
+                    )));
+                }
+                Some(_) => {}
+                None => return Err(missing_vector(name)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn missing_vector(name: &str) -> QsError {
+    QsError::Index(format!(
+        "vector {} is not in the index. Run `qs reindex` to add it",
+        name
+    ))
+}
+
+/// Dimensions of the vectors in the segments of a shard, by name (empty if
+/// the shard has no segments yet).
+pub(crate) fn segment_dimensions(shard_path: &Path) -> Result<HashMap<String, usize>> {
+    let configs: Vec<SegmentVectors> = segment_configs(shard_path)?;
+    Ok(configs
+        .into_iter()
+        .flat_map(|config| config.vector_data)
+        .map(|(name, vector)| (name, vector.size))
+        .collect())
+}
+
+/// Configs of the segments of a shard (none if it has no segments yet).
+fn segment_configs<C: DeserializeOwned>(shard_path: &Path) -> Result<Vec<C>> {
+    let mut configs = Vec::new();
+    let Ok(segments) = std::fs::read_dir(shard_path.join(SEGMENTS_DIR)) else {
+        return Ok(configs);
+    };
+    for segment in segments {
+        let path = segment?.path().join(SEGMENT_STATE_FILE);
+        if !path.exists() {
+            continue;
+        }
+        let state: SegmentState<C> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        configs.push(state.config);
+    }
+    Ok(configs)
+}
+
+/// Stored chunks with their point IDs, and the offset of the next page.
+pub type ScrollPage = (Vec<(u64, ChunkPayload)>, Option<u64>);
+
 /// Storage wrapper around Qdrant Edge.
 pub struct Storage {
     shard: EdgeShard,
+    /// Search parameters from the storage config
+    params: SearchParams,
 }
 
 impl Storage {
-    /// Initialize or load storage for a qs repository.
-    pub fn open(root: &Path, config: &Config) -> Result<Self> {
+    /// Initialize or load storage for a qs repository, with a vector for
+    /// each of `models`.
+    ///
+    /// The storage, index and quantization settings of `config` apply to new
+    /// shards; searches use its `ef` and rescoring settings.
+    ///
+    /// Fails with [`QsError::ModelMismatch`] if the shard was built with
+    /// other models.
+    pub fn open(root: &Path, models: &ShardModels, config: &StorageConfig) -> Result<Self> {
+        // Between the renames of `qs reindex` there is no shard, which must
+        // not be replaced by an empty one
         let shard_path = discover::shard_dir(root);
-        std::fs::create_dir_all(&shard_path)?;
+        if !shard_path.exists() && discover::old_shard_dir(root).exists() {
+            return Err(QsError::Index(
+                "the index was moved aside by an interrupted `qs reindex`. Run `qs reindex` to restore it"
+                    .to_string(),
+            ));
+        }
+        Self::open_at(&shard_path, models, config)
+    }
+
+    /// Initialize or load a shard in the given directory.
+    pub fn open_at(
+        shard_path: &Path,
+        models: &ShardModels,
+        config: &StorageConfig,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(shard_path)?;
+        ShardModel::check(shard_path, models)?;
 
         // Create segment config for the shard
-        let mut vector_data = HashMap::new();
-        vector_data.insert(
-            VECTOR_NAME.to_string(),
-            VectorDataConfig {
-                size: config.dimension,
-                distance: Distance::Cosine,
-                storage_type: VectorStorageType::ChunkedMmap,
-                index: Default::default(),
-                quantization_config: None,
-                multivector_config: None,
-                datatype: None,
-            },
-        );
+        let vector_data = models
+            .iter()
+            .map(|(name, model)| (name.clone(), vector_config(model.dimension, config)))
+            .collect();
 
         let segment_config = SegmentConfig {
             vector_data,
@@ -82,23 +304,26 @@ impl Storage {
             payload_storage_type: PayloadStorageType::Mmap,
         };
 
-        let shard = EdgeShard::load(&shard_path, Some(segment_config))
+        let shard = EdgeShard::load(shard_path, Some(segment_config))
             .map_err(|e| QsError::Storage(e.to_string()))?;
 
-        Ok(Self { shard })
+        Ok(Self {
+            shard,
+            params: search_params(config),
+        })
     }
 
-    /// Insert or update vectors.
-    pub fn upsert(&self, points: Vec<(u64, Vec<f32>, ChunkPayload)>) -> Result<()> {
+    /// Insert or update points, with their vectors by name.
+    pub fn upsert(&self, points: Vec<(u64, NamedVectors, ChunkPayload)>) -> Result<()> {
         if points.is_empty() {
             return Ok(());
         }
 
         let point_structs: Vec<PointStructPersisted> = points
             .into_iter()
-            .map(|(id, vector, payload)| {
+            .map(|(id, vectors, payload)| {
                 let payload_json = serde_json::to_value(&payload).unwrap();
-                make_point(id, vector, payload_json)
+                make_point(id, vectors, payload_json)
             })
             .collect();
 
@@ -113,8 +338,8 @@ impl Storage {
         Ok(())
     }
 
-    /// Search for similar vectors.
-    pub fn search(&self, query: Vec<f32>, limit: usize) -> Result<Vec<SearchResult>> {
+    /// Search for similar vectors of the named vector.
+    pub fn search(&self, vector: &str, query: Vec<f32>, limit: usize) -> Result<Vec<SearchResult>> {
         let query_vec: VectorInternal = query.into();
 
         let results = self
@@ -123,13 +348,13 @@ impl Storage {
                 prefetches: vec![],
                 query: Some(ScoringQuery::Vector(QueryEnum::Nearest(NamedQuery {
                     query: query_vec,
-                    using: Some(VECTOR_NAME.to_string()),
+                    using: Some(vector.to_string()),
                 }))),
                 filter: None,
                 score_threshold: None,
                 limit,
                 offset: 0,
-                params: None,
+                params: Some(self.params),
                 with_vector: WithVector::Bool(false),
                 with_payload: WithPayloadInterface::Bool(true),
             })
@@ -143,6 +368,7 @@ impl Storage {
                     .ok()
                     .map(|payload| SearchResult {
                         score: scored.score,
+                        rerank_score: None,
                         payload,
                     })
             })
@@ -151,6 +377,39 @@ impl Storage {
         Ok(search_results)
     }
 
+    /// Read a page of stored chunks, starting at point ID `offset`, with
+    /// the offset of the next page if there is one.
+    pub fn scroll(&self, offset: Option<u64>, limit: usize) -> Result<ScrollPage> {
+        let (records, next_offset) = self
+            .shard
+            .scroll(ScrollRequestInternal {
+                offset: offset.map(ExtendedPointId::NumId),
+                limit: Some(limit),
+                filter: None,
+                with_payload: Some(WithPayloadInterface::Bool(true)),
+                with_vector: WithVector::Bool(false),
+                order_by: None,
+            })
+            .map_err(|e| QsError::Storage(e.to_string()))?;
+
+        let mut chunks = Vec::with_capacity(records.len());
+        for record in records {
+            let ExtendedPointId::NumId(id) = record.id else {
+                continue;
+            };
+            let payload = record
+                .payload
+                .ok_or_else(|| QsError::Storage(format!("point {} has no payload", id)))?;
+            chunks.push((id, payload_to_chunk(&payload)?));
+        }
+        let next_offset = match next_offset {
+            Some(ExtendedPointId::NumId(id)) => Some(id),
+            _ => None,
+        };
+
+        Ok((chunks, next_offset))
+    }
+
     /// Delete points by IDs.
     pub fn delete(&self, ids: Vec<u64>) -> Result<()> {
         if ids.is_empty() {
@@ -190,12 +449,171 @@ impl Storage {
     pub fn flush(&self) {
         self.shard.flush();
     }
+
+    /// Run the shard's optimizers until they are done: merging small
+    /// segments, and building the HNSW graph and quantized vectors of large
+    /// ones, which search otherwise scans in full.
+    pub fn optimize(&self) -> Result<()> {
+        self.shard
+            .optimize()
+            .map(|_| ())
+            .map_err(|e| QsError::Storage(e.to_string()))
+    }
+
+    /// Storage settings the vectors of the shard at `shard_path` were built
+    /// with, as recorded in its segments, by vector name. Segments built
+    /// with other settings each add theirs. Settings applied at search time
+    /// (`ef`, rescoring) are not recorded, and left at their defaults.
+    pub fn built_settings(shard_path: &Path) -> Result<BTreeMap<String, Vec<StorageConfig>>> {
+        let mut settings: BTreeMap<String, Vec<StorageConfig>> = BTreeMap::new();
+        for config in segment_configs::<SegmentConfig>(shard_path)? {
+            for (name, vector) in config.vector_data {
+                let vector_settings = settings.entry(name).or_default();
+                let built = storage_config(&vector);
+                if !vector_settings.contains(&built) {
+                    vector_settings.push(built);
+                }
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// Segment config of a vector of `dimension` values.
+fn vector_config(dimension: usize, config: &StorageConfig) -> VectorDataConfig {
+    let storage_type = if config.on_disk {
+        VectorStorageType::ChunkedMmap
+    } else {
+        VectorStorageType::InRamChunkedMmap
+    };
+    let hnsw = HnswConfig {
+        m: config.hnsw.m,
+        ef_construct: config.hnsw.ef_construct,
+        full_scan_threshold: DEFAULT_FULL_SCAN_THRESHOLD,
+        max_indexing_threads: 0,
+        on_disk: Some(config.on_disk),
+        payload_m: None,
+        inline_storage: None,
+    };
+
+    VectorDataConfig {
+        size: dimension,
+        distance: Distance::Cosine,
+        storage_type,
+        index: Indexes::Hnsw(hnsw),
+        quantization_config: config.quantization.as_ref().map(quantization_config),
+        multivector_config: None,
+        datatype: None,
+    }
+}
+
+/// Storage settings of a segment's vector config, the reverse of
+/// [`vector_config`].
+fn storage_config(vector: &VectorDataConfig) -> StorageConfig {
+    let hnsw = match &vector.index {
+        Indexes::Hnsw(hnsw) => config::HnswConfig {
+            m: hnsw.m,
+            ef_construct: hnsw.ef_construct,
+            ef: None,
+        },
+        _ => config::HnswConfig::default(),
+    };
+    let quantization = vector.quantization_config.as_ref().map(|quantization| {
+        let (method, always_ram) = match quantization {
+            QuantizationConfig::Scalar(scalar) => (Quantization::Scalar, scalar.scalar.always_ram),
+            QuantizationConfig::Product(product) => {
+                let compression = match product.product.compression {
+                    CompressionRatio::X4 => Compression::X4,
+                    CompressionRatio::X8 => Compression::X8,
+                    CompressionRatio::X16 => Compression::X16,
+                    CompressionRatio::X32 => Compression::X32,
+                    CompressionRatio::X64 => Compression::X64,
+                };
+                (
+                    Quantization::Product { compression },
+                    product.product.always_ram,
+                )
+            }
+            QuantizationConfig::Binary(binary) => (Quantization::Binary, binary.binary.always_ram),
+        };
+        config::QuantizationConfig {
+            method,
+            always_ram: always_ram.unwrap_or(false),
+            rescore: true,
+            oversampling: None,
+        }
+    });
+
+    StorageConfig {
+        on_disk: matches!(
+            vector.storage_type,
+            VectorStorageType::Mmap | VectorStorageType::ChunkedMmap
+        ),
+        hnsw,
+        quantization,
+    }
+}
+
+/// Qdrant quantization config of a configured quantization.
+fn quantization_config(config: &config::QuantizationConfig) -> QuantizationConfig {
+    let always_ram = Some(config.always_ram);
+    match config.method {
+        Quantization::Scalar => QuantizationConfig::Scalar(ScalarQuantization {
+            scalar: ScalarQuantizationConfig {
+                r#type: ScalarType::Int8,
+                quantile: None,
+                always_ram,
+            },
+        }),
+        Quantization::Product { compression } => {
+            let compression = match compression {
+                Compression::X4 => CompressionRatio::X4,
+                Compression::X8 => CompressionRatio::X8,
+                Compression::X16 => CompressionRatio::X16,
+                Compression::X32 => CompressionRatio::X32,
+                Compression::X64 => CompressionRatio::X64,
+            };
+            QuantizationConfig::Product(ProductQuantization {
+                product: ProductQuantizationConfig {
+                    compression,
+                    always_ram,
+                },
+            })
+        }
+        Quantization::Binary => QuantizationConfig::Binary(BinaryQuantization {
+            binary: BinaryQuantizationConfig {
+                always_ram,
+                encoding: None,
+                query_encoding: None,
+            },
+        }),
+    }
+}
+
+/// Search parameters of a storage config.
+fn search_params(config: &StorageConfig) -> SearchParams {
+    let quantization = config
+        .quantization
+        .as_ref()
+        .map(|q| QuantizationSearchParams {
+            ignore: false,
+            rescore: Some(q.rescore),
+            oversampling: q.oversampling,
+        });
+
+    SearchParams {
+        hnsw_ef: config.hnsw.ef,
+        quantization,
+        ..Default::default()
+    }
 }
 
 /// Create a point struct for upserting.
-fn make_point(id: u64, vector: Vec<f32>, payload: Value) -> PointStructPersisted {
-    let mut vectors = HashMap::new();
-    vectors.insert(VECTOR_NAME.to_string(), VectorInternal::from(vector));
+fn make_point(id: u64, vectors: NamedVectors, payload: Value) -> PointStructPersisted {
+    let vectors = vectors
+        .into_iter()
+        .map(|(name, vector)| (name, VectorInternal::from(vector)))
+        .collect();
 
     PointStructPersisted {
         id: ExtendedPointId::NumId(id),
@@ -228,3 +646,94 @@ fn payload_to_chunk(payload: &Payload) -> Result<ChunkPayload> {
     let json_value = Value::Object(json_map);
     serde_json::from_value(json_value).map_err(|e| QsError::Storage(e.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_model_check() {
+        let dir = std::env::temp_dir().join(format!("qs-shard-model-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let models = |spaces: &[(&str, &str, usize)]| -> ShardModels {
+            spaces
+                .iter()
+                .map(|(name, model, dimension)| {
+                    let model = ShardModel {
+                        model: model.to_string(),
+                        dimension: *dimension,
+                        document_template: None,
+                    };
+                    (name.to_string(), model)
+                })
+                .collect()
+        };
+        let code = ("chunks", "jina-embeddings-v2-base-code", 768);
+        let prose = ("prose", "bge-small-en-v1.5", 384);
+
+        // The first models to open a shard are recorded
+        assert!(ShardModel::load(&dir).unwrap().is_empty());
+        ShardModel::check(&dir, &models(&[code, prose])).unwrap();
+        ShardModel::check(&dir, &models(&[code, prose])).unwrap();
+
+        // Vectors may be left out, but not added
+        ShardModel::check(&dir, &models(&[code])).unwrap();
+        let error = ShardModel::check(&dir, &models(&[code, ("docs", "bge", 384)])).unwrap_err();
+        assert!(error.to_string().contains("qs reindex"), "{}", error);
+
+        let error =
+            ShardModel::check(&dir, &models(&[("chunks", "all-MiniLM-L6-v2", 384)])).unwrap_err();
+        assert!(matches!(
+            error,
+            QsError::ModelMismatch {
+                index_dimension: 768,
+                dimension: 384,
+                ..
+            }
+        ));
+        assert!(error.to_string().contains("qs reindex"), "{}", error);
+
+        // Same dimension, but the vectors are not comparable
+        let mismatched = models(&[("chunks", "bge-base-en-v1.5", 768)]);
+        assert!(ShardModel::check(&dir, &mismatched).is_err());
+        assert_eq!(ShardModel::load(&dir).unwrap(), models(&[code, prose]));
+
+        // Chunks embedded in another template are not comparable either
+        let mut templated = models(&[code]);
+        templated.get_mut("chunks").unwrap().document_template = Some("passage: {text}".into());
+        let error = ShardModel::check(&dir, &templated).unwrap_err();
+        assert!(error.to_string().contains("passage: {text}"), "{}", error);
+
+        // Shards from before named vectors recorded one model
+        std::fs::write(
+            dir.join(SHARD_MODEL_FILE),
+            r#"{"model": "jina-embeddings-v2-base-code", "dimension": 768}"#,
+        )
+        .unwrap();
+        assert_eq!(ShardModel::load(&dir).unwrap(), models(&[code]));
+
+        // Shards from before the record are checked by their dimension only
+        std::fs::remove_file(dir.join(SHARD_MODEL_FILE)).unwrap();
+        let segment = dir.join(SEGMENTS_DIR).join("2f1c");
+        std::fs::create_dir_all(&segment).unwrap();
+        std::fs::write(
+            segment.join(SEGMENT_STATE_FILE),
+            r#"{"version": 12, "config": {"vector_data": {"chunks": {"size": 384, "distance": "Cosine"}}}}"#,
+        )
+        .unwrap();
+        let minilm = ("chunks", "all-MiniLM-L6-v2", 384);
+        ShardModel::check(&dir, &models(&[minilm])).unwrap();
+        let error = ShardModel::check(&dir, &models(&[code])).unwrap_err();
+        assert!(matches!(
+            error,
+            QsError::ModelMismatch {
+                index_dimension: 384,
+                dimension: 768,
+                ..
+            }
+        ));
+        assert!(ShardModel::load(&dir).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}